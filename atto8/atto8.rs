@@ -0,0 +1,24 @@
+// shared code behind the Atto-8 toolchain, for embedding machines or calling the assembler
+
+#[path = "../misc/common/common.rs"]
+pub mod common;
+
+// assembler pipeline, from source file to memory image
+#[path = "../asm/assembler.rs"]
+pub mod asm;
+
+// compiler pipeline, from C source files to assembly source
+#[path = "../cc/compiler.rs"]
+pub mod cc;
+
+// instruction-level machine model
+#[path = "../emu/microcomputer.rs"]
+pub mod emu;
+
+// component-level machine model driven by a microcode image
+#[path = "../sim/microcomputer.rs"]
+pub mod sim;
+
+pub use common::{
+  instruction_to_opcode, opcode_to_instruction, Access, Instruction, TickTrap, Tickable,
+};
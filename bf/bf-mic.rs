@@ -1,6 +1,5 @@
-#[path = "../misc/common/common.rs"]
-mod common;
-use common::*;
+use atto8::common;
+use atto8::common::*;
 
 // major parts of this file are identical to '/mic/mic.rs'
 
@@ -131,7 +130,6 @@ fn build_microcode(errors: &mut impl Extend<Error>) -> [u16; common::MIC_SIZE] {
           rest
             .iter()
             .enumerate()
-            .map(|(step, rest)| (step as usize, rest))
             .map(|(step, rest)| {
               let () = rest;
               // when encountering instructions `[` and `]`, the microcode must walk to the matching
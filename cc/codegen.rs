@@ -1,4 +1,4 @@
-use crate::*;
+use crate::cc::*;
 use optimize::Behavior;
 
 #[rustfmt::skip] macro_rules! ret_label { () => { Label::Local(format!("ret"), None) }; }
@@ -17,9 +17,7 @@ pub fn codegen(
   program: TypedProgram,
   _errors: &mut impl Extend<(Pos, Error)>,
 ) -> Vec<Result<Token, String>> {
-  let tokens = codegen::program(program);
-
-  tokens
+  codegen::program(program)
 }
 
 fn program(program: TypedProgram) -> Vec<Result<Token, String>> {
@@ -53,7 +51,7 @@ fn global(global: TypedGlobal) -> Vec<Result<Token, String>> {
             false => std::iter::empty().collect(),
           },
         )
-        .chain([Err(format!(""))])
+        .chain([Err(String::new())])
         .collect()
     }
 
@@ -71,7 +69,7 @@ fn global(global: TypedGlobal) -> Vec<Result<Token, String>> {
             false => std::iter::empty().collect(),
           },
         )
-        .chain([Err(format!(""))])
+        .chain([Err(String::new())])
         .collect()
     }
 
@@ -97,7 +95,7 @@ fn data_global(label: String, value: Vec<TypedExpression>) -> Vec<Result<Token,
   let comment = [Err(match bytes.last() {
     Some(0x00) => format!("# {}", c_quote(&bytes[..bytes.len() - 1], '"')),
     Some(_) => format!("# {}...", c_quote(&bytes, '"')),
-    None => format!(""),
+    None => String::new(),
   })];
 
   let datas: Vec<Result<Token, String>> = tokens
@@ -126,7 +124,7 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
 
     TypedStatement::Compound(statements) => statements
       .into_iter()
-      .flat_map(|statement| codegen::statement(statement))
+      .flat_map(codegen::statement)
       .collect(),
 
     TypedStatement::IfN1(label, condition, if_body, else_body) => codegen::if_n1_statement(
@@ -141,7 +139,7 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
     }
 
     TypedStatement::Break(label, locals_size) => std::iter::empty()
-      .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+      .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
       .chain([
         Ok(Token::LabelRef(codegen::end_label!(&label))),
         Ok(Token::MacroRef(link::jmp_macro!())),
@@ -149,7 +147,7 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
       .collect(),
 
     TypedStatement::Continue(label, locals_size) => std::iter::empty()
-      .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+      .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
       .chain([
         Ok(Token::LabelRef(codegen::cond_label!(&label))),
         Ok(Token::MacroRef(link::jmp_macro!())),
@@ -160,14 +158,20 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
       match (parameters_size, locals_size, expression) {
         (parameters_size, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::n0_expression(expression, 0))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size + locals_size))
+          .chain(std::iter::repeat_n(
+            Ok(Token::Pop),
+            parameters_size + locals_size,
+          ))
           .chain([
             Ok(Token::LabelRef(codegen::ret_label!())),
             Ok(Token::MacroRef(link::jmp_macro!())),
           ])
           .collect(),
         (parameters_size, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size + locals_size))
+          .chain(std::iter::repeat_n(
+            Ok(Token::Pop),
+            parameters_size + locals_size,
+          ))
           .chain([
             Ok(Token::LabelRef(codegen::ret_label!())),
             Ok(Token::MacroRef(link::jmp_macro!())),
@@ -189,7 +193,10 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
         (parameters_size, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::expression(expression, 0))
           .chain(store_to_offset(parameters_size + locals_size - 1))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size + locals_size - 1))
+          .chain(std::iter::repeat_n(
+            Ok(Token::Pop),
+            parameters_size + locals_size - 1,
+          ))
           .chain([
             Ok(Token::LabelRef(codegen::ret_label!())),
             Ok(Token::MacroRef(link::jmp_macro!())),
@@ -203,7 +210,10 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
           ])
           .collect(),
         (parameters_size, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size + locals_size - 1))
+          .chain(std::iter::repeat_n(
+            Ok(Token::Pop),
+            parameters_size + locals_size - 1,
+          ))
           .chain([
             Ok(Token::LabelRef(codegen::ret_label!())),
             Ok(Token::MacroRef(link::jmp_macro!())),
@@ -215,25 +225,25 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
     TypedStatement::FunctionReturnN0(parameters_size, locals_size, expression) => {
       match (parameters_size, locals_size, expression) {
         (0, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (0, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::n0_expression(expression, 0))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (parameters_size, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain(store_to_offset(parameters_size - 1))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size - 1))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), parameters_size - 1))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (parameters_size, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::n0_expression(expression, 0))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain(store_to_offset(parameters_size - 1))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size - 1))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), parameters_size - 1))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
       }
@@ -250,7 +260,7 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
         (0, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::expression(expression, 0))
           .chain(store_to_offset(locals_size - 1))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size - 1))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size - 1))
           .chain([Ok(Token::Swp)])
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
@@ -259,31 +269,31 @@ fn statement(statement: TypedStatement) -> Vec<Result<Token, String>> {
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (0, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size - 1))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size - 1))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (1, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (1, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::expression(expression, 0))
           .chain(store_to_offset(locals_size + 1))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (parameters_size, locals_size, None) => std::iter::empty()
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain(store_to_offset(parameters_size - 2))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size - 2))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), parameters_size - 2))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
         (parameters_size, locals_size, Some(expression)) => std::iter::empty()
           .chain(codegen::expression(expression, 0))
           .chain(store_to_offset(parameters_size + locals_size))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(locals_size))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), locals_size))
           .chain(store_to_offset(parameters_size - 2))
-          .chain(std::iter::repeat(Ok(Token::Pop)).take(parameters_size - 2))
+          .chain(std::iter::repeat_n(Ok(Token::Pop), parameters_size - 2))
           .chain([Ok(Token::MacroRef(link::ret_macro!()))])
           .collect(),
       }
@@ -336,10 +346,7 @@ fn if_n1_statement(
     _ => (false, condition),
   };
 
-  let cf_inverted = match condition {
-    TypedExpression::N1CastN8(_) => true,
-    _ => false,
-  };
+  let cf_inverted = matches!(condition, TypedExpression::N1CastN8(_));
 
   let if_body_behavior = optimize::statement_behavior(&if_body);
 
@@ -375,7 +382,7 @@ fn if_n1_statement(
         .collect(),
       false => std::iter::empty()
         .chain(codegen::n0_expression(precheck, 0))
-        .chain(else_body.map(codegen::statement).unwrap_or_else(Vec::new))
+        .chain(else_body.map(codegen::statement).unwrap_or_default())
         .collect(),
     },
 
@@ -445,10 +452,7 @@ fn while_n1_statement(
     _ => (false, condition),
   };
 
-  let cf_inverted = match condition {
-    TypedExpression::N1CastN8(_) => true,
-    _ => false,
-  };
+  let cf_inverted = matches!(condition, TypedExpression::N1CastN8(_));
 
   let body_behavior = optimize::statement_behavior(&body);
 
@@ -729,7 +733,6 @@ fn n1_expression(
         *expression2,
         temporaries_size,
       ))
-      .into_iter()
       .chain([Ok(Token::XXX(0x00)), Ok(Token::Shl), Ok(Token::AtDyn)])
       .collect(),
 
@@ -746,7 +749,6 @@ fn n1_expression(
         *expression2,
         temporaries_size,
       ))
-      .into_iter()
       .chain([Ok(Token::XXX(0x00)), Ok(Token::Shl), Ok(Token::AtDyn)])
       .collect(),
 
@@ -1,4 +1,4 @@
-use crate::*;
+use crate::cc::*;
 use std::collections::{BTreeMap, BTreeSet};
 
 #[rustfmt::skip] macro_rules! global_label { ($name:expr) => { Label::Global(format!("{}", $name)) }; }
@@ -55,7 +55,7 @@ pub fn link(
 ) -> Vec<Result<Token, String>> {
   let mut dependencies: BTreeMap<(bool, String), BTreeSet<(bool, String)>> = match program {
     TypedProgram(globals) => globals
-      .into_iter()
+      .iter()
       .filter_map(|global| match global {
         TypedGlobal::Data(label, value) => Some((
           (true, label.clone()),
@@ -92,17 +92,14 @@ pub fn link(
 fn statement(statement: &TypedStatement) -> BTreeSet<(bool, String)> {
   match statement {
     TypedStatement::ExpressionN0(expression) => link::expression(expression),
-    TypedStatement::Compound(statements) => statements
-      .into_iter()
-      .flat_map(|statement| link::statement(statement))
-      .collect(),
+    TypedStatement::Compound(statements) => statements.iter().flat_map(link::statement).collect(),
     TypedStatement::IfN1(_label, condition, if_body, else_body) => std::iter::empty()
       .chain(link::expression(condition))
       .chain(link::statement(if_body))
       .chain(
         else_body
           .as_ref()
-          .map(|else_body| link::statement(&else_body))
+          .map(|else_body| link::statement(else_body))
           .unwrap_or_else(BTreeSet::new),
       )
       .collect(),
@@ -177,15 +174,15 @@ fn expression(expression: &TypedExpression) -> BTreeSet<(bool, String)> {
     TypedExpression::N0MacroCall(label, parameters)
     | TypedExpression::N1MacroCall(label, parameters)
     | TypedExpression::N8MacroCall(label, parameters) => parameters
-      .into_iter()
-      .flat_map(|expression| link::expression(expression))
+      .iter()
+      .flat_map(link::expression)
       .chain(std::iter::once((false, label.clone())))
       .collect(),
     TypedExpression::N0FunctionCall(designator, parameters)
     | TypedExpression::N1FunctionCall(designator, parameters)
     | TypedExpression::N8FunctionCall(designator, parameters) => parameters
-      .into_iter()
-      .flat_map(|expression| link::expression(expression))
+      .iter()
+      .flat_map(link::expression)
       .chain(link::expression(designator))
       .collect(),
   }
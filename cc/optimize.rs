@@ -1,4 +1,4 @@
-use crate::*;
+use crate::cc::*;
 use std::collections::HashSet;
 
 pub fn optimize(program: TypedProgram, _errors: &mut impl Extend<(Pos, Error)>) -> TypedProgram {
@@ -728,19 +728,13 @@ fn behavior_sequenced(a: BehaviorSet, b: BehaviorSet) -> BehaviorSet {
 fn behavior_difference(a: BehaviorSet, b: HashSet<Behavior>) -> BehaviorSet {
   // remove from possible behaviors, leaving undefined behavior untouched
 
-  match a {
-    Some(a) => Some(a.into_iter().filter(|x| !b.contains(x)).collect()),
-    None => None,
-  }
+  a.map(|a| a.into_iter().filter(|x| !b.contains(x)).collect())
 }
 
 fn behavior_union(a: BehaviorSet, b: HashSet<Behavior>) -> BehaviorSet {
   // add to possible behaviors, leaving undefined behavior untouched
 
-  match a {
-    Some(a) => Some(a.into_iter().chain(b).collect()),
-    None => None,
-  }
+  a.map(|a| a.into_iter().chain(b).collect())
 }
 
 pub fn behavior_contains(a: &BehaviorSet, b: &Behavior) -> bool {
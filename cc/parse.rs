@@ -1,4 +1,4 @@
-use crate::*;
+use crate::cc::*;
 use std::rc::Rc;
 
 // utilities
@@ -18,7 +18,8 @@ fn b<T, U, V, F: FnOnce(U) -> V + Clone + 'static, G: FnOnce(T) -> U + Clone + '
 }
 
 #[derive(Clone)]
-pub struct Parser<T: Clone + 'static>(pub Rc<dyn Fn(&str) -> ParseResult<T>>);
+pub struct Parser<T: Clone + 'static>(pub Rc<ParseFn<T>>);
+pub type ParseFn<T> = dyn Fn(&str) -> ParseResult<T>;
 pub type Expecteds = Vec<String>;
 pub enum ParseResult<T> {
   Ok((T, String)),                        // parser succeeded without ever backtracking
@@ -124,7 +125,7 @@ impl<T> ParseResult<T> {
 pub fn format_expecteds((expecteds, input): (Expecteds, String)) -> String {
   let expecteds = match expecteds.len() {
     0 => panic!("No expecteds"),
-    1 => format!("{}", expecteds[0]),
+    1 => expecteds[0].to_string(),
     _ => format!(
       "{}, or {}",
       expecteds[..expecteds.len() - 1].join(", "),
@@ -134,8 +135,8 @@ pub fn format_expecteds((expecteds, input): (Expecteds, String)) -> String {
 
   let got = match input.len() {
     0 => "end of input".to_string(),
-    0..=16 => c_quote(input.as_bytes(), '`'),
-    _ => c_quote(input[0..16].as_bytes(), '`') + "...",
+    1..=16 => c_quote(input.as_bytes(), '`'),
+    _ => c_quote(&input.as_bytes()[0..16], '`') + "...",
   };
 
   format!("Expected {} (got {})", expecteds, got)
@@ -143,7 +144,7 @@ pub fn format_expecteds((expecteds, input): (Expecteds, String)) -> String {
 
 impl<T: Clone + 'static> Parser<T> {
   pub fn parse(&self, input: &str) -> Result<T, String> {
-    match self.0(&input).into_result() {
+    match self.0(input).into_result() {
       Ok((r#match, input)) => match &input[..] {
         "" => Ok(r#match),
         _ => panic!("Input not fully parsed"), // parser must be exhaustive
@@ -223,14 +224,14 @@ impl<T: Clone + 'static> Parser<T> {
 // elementary parsers
 
 pub fn any() -> Parser<char> {
-  Parser(Rc::new(|input: &str| match &input[..] {
+  Parser(Rc::new(|input: &str| match input {
     "" => ParseResult::Err((vec![format!("any character")], input.to_string())),
     _ => ParseResult::Ok((input.chars().next().unwrap(), input[1..].to_string())),
   }))
 }
 
 pub fn eof() -> Parser<()> {
-  Parser(Rc::new(|input: &str| match &input[..] {
+  Parser(Rc::new(|input: &str| match input {
     "" => ParseResult::Ok(((), input.to_string())),
     _ => ParseResult::Err((vec![format!("end of input")], input.to_string())),
   }))
@@ -254,7 +255,7 @@ pub fn char(char: char) -> Parser<()> {
 pub fn string(string: &'static str) -> Parser<()> {
   string
     .chars()
-    .map(|char| parse::char(char))
+    .map(parse::char)
     .reduce(|acc, parser| acc.and_then(|_| parser))
     .unwrap()
     .name(c_quote(string.as_bytes(), '"'))
@@ -271,26 +272,27 @@ pub fn char_none_of(chars: &'static str) -> Parser<char> {
   parse::satisfy(move |c| !chars.contains(c)).name(format!("none of '{}'", chars))
 }
 
+#[allow(clippy::zero_prefixed_literal)]
 pub fn digit(radix: u32) -> Parser<char> {
   parse::satisfy(move |c| c.is_digit(radix)).name(match radix {
-    0b10 => format!("binary digit"),
-    0o10 => format!("octal digit"),
-    0_10 => format!("decimal digit"),
-    0x10 => format!("hexadecimal digit"),
+    0b10 => "binary digit".to_string(),
+    0o10 => "octal digit".to_string(),
+    0_10 => "decimal digit".to_string(),
+    0x10 => "hexadecimal digit".to_string(),
     o_10 => format!("base-{} digit", o_10),
   })
 }
 
 pub fn alphabetic() -> Parser<char> {
-  parse::satisfy(|c| c.is_alphabetic()).name(format!("alphabetic character"))
+  parse::satisfy(|c| c.is_alphabetic()).name("alphabetic character".to_string())
 }
 
 pub fn newline() -> Parser<()> {
-  parse::char('\n').name(format!("newline character"))
+  parse::char('\n').name("newline character".to_string())
 }
 
 pub fn whitespace() -> Parser<char> {
-  parse::satisfy(|c| c.is_whitespace()).name(format!("whitespace character"))
+  parse::satisfy(|c| c.is_whitespace()).name("whitespace character".to_string())
 }
 
 pub fn ws<T: Clone + 'static>(parser: Parser<T>) -> Parser<T> {
@@ -347,7 +349,7 @@ pub fn unop<T: Clone + 'static>(
   operator: Parser<Rc<dyn Fn(T) -> T>>,
 ) -> Parser<T> {
   operator
-    .name(format!("unary operator"))
+    .name("unary operator".to_string())
     .and_then(|constructor| parser.map(move |operand| constructor(operand)))
 }
 
@@ -358,7 +360,7 @@ pub fn binop<T: Clone + 'static>(
   parser.clone().and_then(|first| {
     parse::many(
       operator
-        .name(format!("binary operator"))
+        .name("binary operator".to_string())
         .and_then(|constructor| parser.map(|second| (constructor, second))),
     )
     .map(|rest| {
@@ -391,7 +393,7 @@ fn translation_unit() -> Parser<Program> {
       .or_else(|_| parse::assembly_global()),
   )
   .and_then(|globals| parse::eof().map(move |_| globals))
-  .map(|globals| Program(globals))
+  .map(Program)
 }
 
 fn function_declaration_global() -> Parser<Global> {
@@ -429,7 +431,7 @@ fn function_declaration_global() -> Parser<Global> {
         })
       })
     })
-    .name(format!("function declaration"))
+    .name("function declaration".to_string())
 }
 
 fn function_definition_global() -> Parser<Global> {
@@ -451,7 +453,7 @@ fn function_definition_global() -> Parser<Global> {
                 Parser::pure(())
                   .and_then(|_| parse::ws(parse::char(')').info("to end parameter list")))
                   .and_then(|_| {
-                    parse::statement().name(format!("statement to begin function body"))
+                    parse::statement().name("statement to begin function body".to_string())
                   })
                   .map(move |statement| {
                     Global::FunctionDefinition(
@@ -470,7 +472,7 @@ fn function_definition_global() -> Parser<Global> {
         })
       })
     })
-    .name(format!("function definition"))
+    .name("function definition".to_string())
 }
 
 fn parameter_list() -> Parser<Vec<Object>> {
@@ -483,7 +485,7 @@ fn parameter_list() -> Parser<Vec<Object>> {
     }),
     parse::ws(parse::char(',').info("to continue parameter list")),
   )
-  .name(format!("parameter list"))
+  .name("parameter list".to_string())
 }
 
 fn global_declaration_global() -> Parser<Global> {
@@ -495,7 +497,7 @@ fn global_declaration_global() -> Parser<Global> {
           .map(move |_| Global::GlobalDeclaration(Object(type_name, identifier)))
       })
     })
-    .name(format!("global declaration"))
+    .name("global declaration".to_string())
 }
 
 fn global_definition_global() -> Parser<Global> {
@@ -511,7 +513,7 @@ fn global_definition_global() -> Parser<Global> {
         })
       })
     })
-    .name(format!("global definition"))
+    .name("global definition".to_string())
 }
 
 fn type_name() -> Parser<Type> {
@@ -568,13 +570,13 @@ fn type_name() -> Parser<Type> {
         .map(|_| Type::Pointer(Box::new(r#type)))
         .or_else(|_| Parser::pure(type1))
     })
-    .name(format!("type name"))
+    .name("type name".to_string())
 }
 
 fn assembly_global() -> Parser<Global> {
   parse::assembly_literal()
-    .map(|assembly| Global::GlobalAssembly(assembly))
-    .name(format!("assembly global"))
+    .map(Global::GlobalAssembly)
+    .name("assembly global".to_string())
 }
 
 fn assembly_literal() -> Parser<String> {
@@ -597,8 +599,8 @@ fn compound_statement() -> Parser<Statement> {
         parse::ws(parse::char('}').info("to end block")).map(move |_| statements)
       })
     })
-    .map(|statements| Statement::Compound(statements))
-    .name(format!("compound statement"))
+    .map(Statement::Compound)
+    .name("compound statement".to_string())
 }
 
 fn declaration() -> Parser<Statement> {
@@ -616,7 +618,7 @@ fn declaration() -> Parser<Statement> {
         })
       })
     })
-    .name(format!("declaration"))
+    .name("declaration".to_string())
 }
 
 fn statement() -> Parser<Statement> {
@@ -628,7 +630,7 @@ fn statement() -> Parser<Statement> {
     .or_else(|_| parse::selection_statement())
     .or_else(|_| parse::expression_statement())
     .or_else(|_| parse::assembly_statement()) // TODO nonstandard
-    .name(format!("statement"))
+    .name("statement".to_string())
 }
 
 fn jump_statement() -> Parser<Statement> {
@@ -654,7 +656,7 @@ fn jump_statement() -> Parser<Statement> {
             parse::ws(parse::char(';').info("to end statement")).map(|_| expression)
           })
         })
-        .map(|expression| Statement::Return(expression))
+        .map(Statement::Return)
     })
 }
 
@@ -753,8 +755,8 @@ fn expression_statement() -> Parser<Statement> {
 
 fn assembly_statement() -> Parser<Statement> {
   parse::assembly_literal()
-    .map(|assembly| Statement::Assembly(assembly))
-    .name(format!("assembly statement"))
+    .map(Statement::Assembly)
+    .name("assembly statement".to_string())
 }
 
 fn expression() -> Parser<Expression> {
@@ -963,17 +965,17 @@ fn postfix_expression() -> Parser<Expression> {
               .map(|_| Expression::FunctionCall(Box::new(expression1), arguments))
           })
       })
-      .and_then(|expression| postfix(expression))
+      .and_then(postfix)
       .or_else(move |_| Parser::pure(expression2.clone()))
   }
 
-  parse::primary_expression().and_then(|expression| postfix(expression))
+  parse::primary_expression().and_then(postfix)
 }
 
 fn primary_expression() -> Parser<Expression> {
   // TODO cases missing <floating-constant> and <enumeration-constant>
   Parser::expected(vec![])
-    .or_else(|_| parse::identifier().map(|identifier| Expression::Identifier(identifier)))
+    .or_else(|_| parse::identifier().map(Expression::Identifier))
     .or_else(|_| parse::integer_constant())
     .or_else(|_| parse::character_constant())
     .or_else(|_| parse::string_literal())
@@ -996,11 +998,11 @@ fn identifier() -> Parser<String> {
           .or_else(|_| parse::digit(10))
           .or_else(|_| parse::alphabetic())
           .or_else(|_| parse::char('_').map(|_| '_'))
-          .name(format!("identifier character")),
+          .name("identifier character".to_string()),
       ))
       .map(move |rest| std::iter::once(first).chain(rest).collect())
     })
-    .name(format!("identifier"))
+    .name("identifier".to_string())
 }
 
 fn integer_constant() -> Parser<Expression> {
@@ -1031,14 +1033,14 @@ fn integer_constant() -> Parser<Expression> {
     })
     .or_else(|_| {
       // <decimal-constant>
-      parse::satisfy(|c| c.is_digit(10) && c != '0')
+      parse::satisfy(|c| c.is_ascii_digit() && c != '0')
         .and_then(|first| parse::ws(parse::many(parse::digit(10))).map(move |rest| (first, rest)))
         .map(|(first, rest)| std::iter::once(first).chain(rest).collect::<String>())
-        .map(|digits| u8::from_str_radix(&digits, 10))
+        .map(|digits| digits.parse::<u8>())
     })
     .map(|digits| digits.unwrap_or_else(|_| panic!("Could not parse integer constant")))
-    .map(|value| Expression::IntegerConstant(value))
-    .name(format!("integer constant"))
+    .map(Expression::IntegerConstant)
+    .name("integer constant".to_string())
 }
 
 fn character_constant() -> Parser<Expression> {
@@ -1046,14 +1048,14 @@ fn character_constant() -> Parser<Expression> {
     .and_then(|_| parse::char('\''))
     .and_then(|_| {
       parse::char_none_of("\'\\\n")
-        .name(format!("character constant character"))
+        .name("character constant character".to_string())
         .or_else(|_| parse::escape_sequence())
     })
     .and_then(|char| {
       parse::ws(parse::char('\'').info("to end character constant"))
         .map(move |_| Expression::CharacterConstant(char))
     })
-    .name(format!("character constant"))
+    .name("character constant".to_string())
 }
 
 fn string_literal() -> Parser<Expression> {
@@ -1064,7 +1066,7 @@ fn string_literal() -> Parser<Expression> {
       .and_then(|_| {
         parse::many(
           parse::char_none_of("\"\\\n")
-            .name(format!("string literal character"))
+            .name("string literal character".to_string())
             .or_else(|_| parse::escape_sequence()),
         )
       })
@@ -1073,7 +1075,7 @@ fn string_literal() -> Parser<Expression> {
       }),
   )
   .map(|strings| Expression::StringLiteral(strings.into_iter().flatten().chain(['\0']).collect()))
-  .name(format!("string literal"))
+  .name("string literal".to_string())
 }
 
 fn escape_sequence() -> Parser<char> {
@@ -1094,16 +1096,14 @@ fn escape_sequence() -> Parser<char> {
             .or_else(|_| parse::char('r').map(|_| '\r'))
             .or_else(|_| parse::char('t').map(|_| '\t'))
             .or_else(|_| parse::char('v').map(|_| '\x0B'))
-            .name(format!("one of ''\"?\\abfnrtv'"))
+            .name("one of ''\"?\\abfnrtv'".to_string())
         })
         .or_else(|_| {
           // <octal-escape-sequence>
           parse::digit(0o10).and_then(|first| {
             parse::maybe(parse::digit(0o10)).and_then(move |second| {
               parse::maybe(parse::digit(0o10)).map(move |third| {
-                let digits = std::iter::once(first)
-                  .chain(second.into_iter())
-                  .chain(third.into_iter());
+                let digits = std::iter::once(first).chain(second).chain(third);
                 let digits = digits.collect::<String>();
                 u8::from_str_radix(&digits, 0o10)
                   .unwrap_or_else(|_| panic!("Could not parse escape sequence"))
@@ -1119,7 +1119,7 @@ fn escape_sequence() -> Parser<char> {
             .and_then(|_| parse::digit(0x10))
             .and_then(|first| {
               parse::maybe(parse::digit(0x10)).map(move |second| {
-                let digits = std::iter::once(first).chain(second.into_iter());
+                let digits = std::iter::once(first).chain(second);
                 let digits = digits.collect::<String>();
                 u8::from_str_radix(&digits, 0x10)
                   .unwrap_or_else(|_| panic!("Could not parse escape sequence"))
@@ -1128,5 +1128,5 @@ fn escape_sequence() -> Parser<char> {
             })
         })
     })
-    .name(format!("escape sequence"))
+    .name("escape sequence".to_string())
 }
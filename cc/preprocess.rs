@@ -1,4 +1,4 @@
-use crate::*;
+use crate::cc::*;
 use parse::Parser;
 use std::collections::HashMap;
 
@@ -22,7 +22,7 @@ pub fn preprocess(
       .or_else(|_| preprocess::error_directive())
       .or_else(|_| preprocess::null_directive())
       .or_else(|_| preprocess::text_line_directive())
-      .or_else(|_| parse::eof().map(|_| Directive::EOF))
+      .or_else(|_| parse::eof().map(|_| Directive::Eof))
   });
 
   defines.insert("__STDC_NO_ATOMICS__".to_string(), vec![Ok(1.to_string())]);
@@ -40,7 +40,7 @@ pub fn preprocess(
       pos.unwrap_or(Pos(File("[bootstrap]".into()), 0, 0)),
       Error(format!("Unable to read file '{}'", file)),
     )]);
-    format!("")
+    String::new()
   });
 
   // adjacent string literals are concatenated later by the parser
@@ -82,18 +82,16 @@ pub fn preprocess(
           Directive::Pragma(_arguments) => "".to_string(),
 
           Directive::Error(message) => {
-            let message = preprocess_text_line_directive(message, defines, errors);
+            let message = preprocess_text_line_directive(message, defines);
             errors.extend([(pos, Error(format!("#error {}", message)))]);
             "".to_string()
           }
 
           Directive::Null => "".to_string(),
 
-          Directive::TextLine(text_line) => {
-            preprocess_text_line_directive(text_line, defines, errors)
-          }
+          Directive::TextLine(text_line) => preprocess_text_line_directive(text_line, defines),
 
-          Directive::EOF => {
+          Directive::Eof => {
             match &input[..] {
               "" => break preprocessed,
               _ => panic!("Input not fully parsed"),
@@ -122,7 +120,6 @@ pub fn preprocess(
 fn preprocess_text_line_directive(
   text_line: TextLine,
   defines: &mut HashMap<String, TextLine>,
-  errors: &mut impl Extend<(Pos, Error)>,
 ) -> String {
   // resolve defines recursively in text line and return preprocessed text line
 
@@ -133,7 +130,7 @@ fn preprocess_text_line_directive(
         Ok(identifier) => match defines.remove(&identifier) {
           Some(text_line) => {
             // prevents infinite recursion
-            let preprocessed = preprocess_text_line_directive(text_line.clone(), defines, errors);
+            let preprocessed = preprocess_text_line_directive(text_line.clone(), defines);
             defines.insert(identifier, text_line);
             preprocessed
           }
@@ -155,7 +152,7 @@ fn preprocess_include_directive(
   // resolve defines in include directive and preprocess included file
 
   use std::path::Path;
-  let filename = preprocess_text_line_directive(filename, defines, errors);
+  let filename = preprocess_text_line_directive(filename, defines);
 
   match preprocess::include_directive_filename().parse(&filename) {
     Ok(filename) => {
@@ -169,7 +166,7 @@ fn preprocess_include_directive(
     }
     Err(error) => {
       errors.extend([(Pos(File("[preprocess]".into()), 0, 0), Error(error))]);
-      format!("")
+      String::new()
     }
   }
 }
@@ -185,15 +182,15 @@ enum Directive {
   Error(TextLine),
   Null,
   TextLine(TextLine),
-  EOF,
+  Eof,
 }
 
 fn any() -> Parser<char> {
-  parse::char_not('\n').name(format!("non-newline character"))
+  parse::char_not('\n').name("non-newline character".to_string())
 }
 
 pub fn whitespace() -> Parser<char> {
-  parse::satisfy(|c| c.is_whitespace() && c != '\n').name(format!("non-newline whitespace"))
+  parse::satisfy(|c| c.is_whitespace() && c != '\n').name("non-newline whitespace".to_string())
 }
 
 pub fn ws<T: Clone + 'static>(parser: Parser<T>) -> Parser<T> {
@@ -281,9 +278,9 @@ fn text_line() -> Parser<TextLine> {
   // TODO does not obey grammar
   parse::many(
     Parser::expected(vec![])
-      .or_else(|_| preprocess::whitespace().map(|character| Err(character)))
-      .or_else(|_| preprocess::identifier().map(|identifier| Ok(identifier)))
-      .or_else(|_| preprocess::any().map(|character| Err(character))),
+      .or_else(|_| preprocess::whitespace().map(Err))
+      .or_else(|_| preprocess::identifier().map(Ok))
+      .or_else(|_| preprocess::any().map(Err)),
   )
 }
 
@@ -298,7 +295,7 @@ fn identifier() -> Parser<String> {
       .or_else(|_| parse::char('_').map(|_| '_')),
   )
   .map(|chars| chars.iter().collect::<String>())
-  .name(format!("preprocessor identifier"))
+  .name("preprocessor identifier".to_string())
 }
 
 fn include_directive_filename() -> Parser<String> {
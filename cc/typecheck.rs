@@ -1,4 +1,4 @@
-use crate::*;
+use crate::cc::*;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
 pub fn typecheck(program: Program, errors: &mut impl Extend<(Pos, Error)>) -> TypedProgram {
@@ -15,6 +15,7 @@ struct State {
 }
 
 #[derive(Clone, PartialEq, Debug)]
+#[allow(clippy::enum_variant_names)]
 enum StackEntry {
   MacroBoundary(Type, Vec<Object>),
   FunctionBoundary(Type, Vec<Object>), // parameters in "push" order (reverse of declaration)
@@ -181,7 +182,7 @@ fn function_declaration_global(
   is_variadic: bool,
   state: &mut State,
   errors: &mut impl Extend<(Pos, Error)>,
-) -> () {
+) {
   let parameter_types: Vec<Type> = parameters
     .into_iter()
     .map(|Object(r#type, _name)| r#type)
@@ -235,11 +236,11 @@ fn function_definition_global(
   if is_variadic {
     errors.extend([(
       Pos(File("[todo]".into()), 0, 0),
-      Error(format!("Variadic function definitions unimplemented")),
+      Error("Variadic function definitions unimplemented".to_string()),
     )]);
   }
 
-  if state.definitions.get(&name).is_some() {
+  if state.definitions.contains(&name) {
     errors.extend([(
       Pos(File("[pos]".into()), 0, 0),
       Error(format!("Redefinition of function `{}`", name)),
@@ -277,7 +278,7 @@ fn global_declaration_global(
   Object(global_type, name): Object,
   state: &mut State,
   errors: &mut impl Extend<(Pos, Error)>,
-) -> () {
+) {
   state
     .declarations
     .entry(name.clone())
@@ -306,7 +307,7 @@ fn global_definition_global(
 
   let value = typecheck_expression_cast(global_type.clone(), value, state, errors);
 
-  if state.definitions.get(&name).is_some() {
+  if state.definitions.contains(&name) {
     errors.extend([(
       Pos(File("[pos]".into()), 0, 0),
       Error(format!("Redefinition of global `{}`", name)),
@@ -412,7 +413,7 @@ fn compound_statement(
 
   let statements = body_statements
     .into_iter()
-    .chain(uninit_statements.into_iter())
+    .chain(uninit_statements)
     .collect();
 
   TypedStatement::Compound(statements)
@@ -457,10 +458,7 @@ fn if_statement(
   let if_body = typecheck::statement(if_body, state, errors);
   let else_body = else_body.map(|else_body| typecheck::statement(else_body, state, errors));
 
-  let statement =
-    TypedStatement::IfN1(label, condition, Box::new(if_body), else_body.map(Box::new));
-
-  statement
+  TypedStatement::IfN1(label, condition, Box::new(if_body), else_body.map(Box::new))
 }
 
 fn declaration_statement(
@@ -506,7 +504,7 @@ fn break_statement(state: &mut State, errors: &mut impl Extend<(Pos, Error)>) ->
       StackEntry::MacroBoundary(_, _) | StackEntry::FunctionBoundary(_, _) => {
         errors.extend([(
           Pos(File("[pos]".into()), 0, 0),
-          Error(format!("Use of `break` not within a loop")),
+          Error("Use of `break` not within a loop".to_string()),
         )]);
         Some("".to_string())
       }
@@ -531,7 +529,7 @@ fn continue_statement(state: &mut State, errors: &mut impl Extend<(Pos, Error)>)
       StackEntry::MacroBoundary(_, _) | StackEntry::FunctionBoundary(_, _) => {
         errors.extend([(
           Pos(File("[pos]".into()), 0, 0),
-          Error(format!("Use of `continue` not within a loop")),
+          Error("Use of `continue` not within a loop".to_string()),
         )]);
         Some("".to_string())
       }
@@ -784,7 +782,7 @@ fn expression(
         Range::I8 => {
           errors.extend([(
             Pos(File("[todo]".into()), 0, 0),
-            Error(format!("Signed division unimplemented")),
+            Error("Signed division unimplemented".to_string()),
           )]);
           TypedExpression::U8Division(Box::new(expression1), Box::new(expression2))
         }
@@ -805,7 +803,7 @@ fn expression(
         Range::I8 => {
           errors.extend([(
             Pos(File("[todo]".into()), 0, 0),
-            Error(format!("Signed modulo unimplemented")),
+            Error("Signed modulo unimplemented".to_string()),
           )]);
           TypedExpression::U8Modulo(Box::new(expression1), Box::new(expression2))
         }
@@ -1165,7 +1163,7 @@ fn cast_expression(
     }
 
     (type1, r#type) if type1 == *r#type => expression1,
-    (type1, r#type) if width(&type1) == width(&r#type) => expression1,
+    (type1, r#type) if width(&type1) == width(r#type) => expression1,
 
     (Type::Int, Type::Bool)
     | (Type::UnsignedInt, Type::Bool)
@@ -1574,13 +1572,16 @@ fn typecheck_expression_cast(
   state: &mut State,
   errors: &mut impl Extend<(Pos, Error)>,
 ) -> TypedExpression {
-  let (r#type, expression) = typecheck::expression(
-    Expression::Cast(r#type, Box::new(expression)),
+  let (cast_type, expression) = typecheck::expression(
+    Expression::Cast(r#type.clone(), Box::new(expression)),
     state,
     errors,
   );
 
-  assert_eq!(r#type, r#type, "Expected expression to have requested type");
+  assert_eq!(
+    cast_type, r#type,
+    "Expected expression to have requested type"
+  );
 
   expression
 }
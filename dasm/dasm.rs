@@ -1,71 +1,162 @@
-#[path = "../misc/common/common.rs"]
-mod common;
-use common::*;
+use atto8::asm;
+use atto8::common;
+use atto8::common::*;
 
 fn main() {
   let args: Vec<String> = std::env::args().collect();
+  let (args, flags) = common::parse_flags(args);
   if args.len() != 3 {
-    println!("Dasm: Usage: dasm <memory image file> <disassembly output file>");
+    println!(
+      "Dasm: Usage: dasm <memory image file> <disassembly output file> [--bank-size=<bytes>] [--debug=<debug info file>]"
+    );
     std::process::exit(1);
   }
 
   let memory_image_file: &String = &args[1];
   let disassembly_output_file: &String = &args[2];
 
-  let memory_image: [u8; common::MEM_SIZE] = std::fs::read(memory_image_file)
-    .unwrap_or_else(|_| {
-      println!("Dasm: Error: Unable to read file '{}'", memory_image_file);
+  let (memory_image, banks) =
+    common::load_memory_image(memory_image_file, &flags).unwrap_or_else(|error| {
+      println!("Dasm: Error: {}", error);
+      std::process::exit(1);
+    });
+
+  let debug_info: DebugInfo = common::load_debug_info(&flags)
+    .unwrap_or_else(|error| {
+      println!("Dasm: Error: {}", error);
       std::process::exit(1);
     })
-    .try_into()
-    .unwrap_or_else(|_| {
-      println!(
-        "Dasm: Error: Memory image '{}' has incorrect size",
-        memory_image_file,
+    .unwrap_or_default();
+
+  let disassembly = match &banks {
+    None => format!(
+      "{}\n{}",
+      Token::MacroDef(Macro("main".to_string())),
+      disassemble(&memory_image, 0x00, &debug_info)
+    ),
+    Some(banks) => {
+      // main memory skips the expansion window, which every bank is then disassembled into.
+      // debug info only covers main memory
+      let (register, window) = (banks.register(), banks.window());
+      let mut disassembly = format!(
+        "{}\n{}  {} {}\n{}",
+        Token::MacroDef(Macro("main".to_string())),
+        disassemble(&memory_image[..register], 0x00, &debug_info),
+        Token::XXX(common::DISPLAY_BUFFER as u8),
+        Token::AtOrg,
+        disassemble(
+          &memory_image[common::DISPLAY_BUFFER..],
+          common::DISPLAY_BUFFER as u8,
+          &debug_info
+        ),
       );
-      std::process::exit(1);
+      for (index, page) in banks.pages().iter().enumerate() {
+        disassembly += &format!(
+          "\n{}\n  {} {}\n{}",
+          Token::MacroDef(Macro(format!("bank{}", index))),
+          Token::XXX(window as u8),
+          Token::AtOrg,
+          disassemble(page, window as u8, &DebugInfo::default())
+        );
+      }
+      disassembly
+    }
+  };
+
+  let disassembly = format!("# Generated by Dasm\n\n{}", disassembly);
+
+  std::fs::write(disassembly_output_file, disassembly).unwrap();
+
+  println!("Dasm: Done");
+}
+
+fn disassemble(opcodes: &[u8], start: u8, debug_info: &DebugInfo) -> String {
+  // one line per instruction, `start` being the address of the first byte. pushes of a label
+  // address within debug info are written out as label references, provided they assemble back
+  // into the same bytes. labels never referenced are emitted as comments, as the assembler
+  // rejects unused label definitions
+
+  let labels_at = |address: u8| {
+    (debug_info.labels.iter())
+      .filter(move |(label_address, _)| *label_address == address)
+      .map(|(_, label)| label)
+  };
+
+  let mut lines: Vec<(u8, Token, &[u8])> = vec![];
+  let mut index = 0;
+  while index < opcodes.len() {
+    let address = start.wrapping_add(index as u8);
+    // consecutive references to the same label would be optimized into `ldo`s
+    let previous = lines.last().map(|(_, token, _)| token);
+    let reference = debug_info.origin_at(address).and_then(|_| {
+      (debug_info.labels.iter())
+        .map(|(label_address, label)| {
+          let push: Vec<u8> = (asm::push_immediate(*label_address).into_iter())
+            .map(|instruction| common::instruction_to_opcode(Ok(instruction)))
+            .collect();
+          (push, Token::LabelRef(label.clone()))
+        })
+        .find(|(push, token)| opcodes[index..].starts_with(push) && previous != Some(token))
     });
 
-  let opcodes: Vec<u8> = Vec::from(memory_image);
+    let (token, len) = match reference {
+      Some((push, token)) => (token, push.len()),
+      // bytes that would not assemble back into themselves are written out as data
+      None => match common::opcode_to_instruction(opcodes[index]) {
+        Ok(instruction)
+          if common::instruction_to_opcode(Ok(instruction.clone())) == opcodes[index] =>
+        {
+          (common::instruction_to_token(Ok(instruction)), 1)
+        }
+        _ => (Token::AtDD(opcodes[index]), 1),
+      },
+    };
+    lines.push((address, token, &opcodes[index..index + len]));
+    index += len;
+  }
 
-  let instructions: Vec<Result<Instruction, u8>> = opcodes
-    .into_iter()
-    .map(common::opcode_to_instruction)
+  let referenced: Vec<&Label> = (lines.iter())
+    .filter_map(|(_, token, _)| match token {
+      Token::LabelRef(label) => Some(label),
+      _ => None,
+    })
     .collect();
 
-  let tokens: Vec<Token> = instructions
-    .into_iter()
-    .map(common::instruction_to_token)
-    .collect();
+  (lines.iter())
+    .map(|(address, token, bytes)| {
+      let labels = labels_at(*address)
+        .map(|label| match referenced.contains(&label) {
+          true => format!("  {}\n", Token::LabelDef(label.clone())),
+          false => format!("  # {}\n", Token::LabelDef(label.clone())),
+        })
+        .collect::<String>();
+
+      // `@dyn` keeps instructions from being optimized away, and neither applies to data
+      let directive = match token {
+        Token::LabelRef(_) | Token::AtDD(_) => "".to_string(),
+        _ => format!(" {}", Token::AtDyn),
+      };
+
+      let bytes = (bytes.iter())
+        .map(|byte| Token::AtDD(*byte).to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
 
-  let mnemonics: Vec<Mnemonic> = tokens.into_iter().map(common::token_to_mnemonic).collect();
+      let origin = match debug_info.origin_at(*address) {
+        Some((r#macro, pos)) => format!(" {} {}", r#macro, pos),
+        None => "".to_string(),
+      };
 
-  let disassembly: String = mnemonics
-    .into_iter()
-    .enumerate()
-    .zip(memory_image.into_iter())
-    .map(|((index, mnemonic), opcode)| {
       format!(
-        "  {} {} # {} {} {}",
-        mnemonic,
-        Token::AtDyn,
-        Token::XXX(index as u8),
+        "{}  {}{} # {} {} {}{}\n",
+        labels,
+        common::token_to_mnemonic(token.clone()),
+        directive,
+        Token::XXX(*address),
         Token::AtOrg,
-        Token::AtDD(opcode),
+        bytes,
+        origin,
       )
     })
-    .map(|line| line.to_string() + "\n")
-    .collect::<String>();
-
-  let disassembly = format!(
-    "{}\n{}",
-    Token::MacroDef(Macro("main".to_string())),
-    disassembly
-  );
-
-  let disassembly = format!("# Generated by Dasm\n\n{}", disassembly);
-
-  std::fs::write(disassembly_output_file, disassembly).unwrap();
-
-  println!("Dasm: Done");
+    .collect()
 }
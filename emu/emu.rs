@@ -1,348 +1,173 @@
-use std::collections::VecDeque;
-
-#[path = "../misc/common/common.rs"]
-mod common;
-use common::*;
+use atto8::common;
+use atto8::common::Tickable;
+use atto8::emu::*;
 
 fn main() {
   let args: Vec<String> = std::env::args().collect();
-  if args.len() != 2 {
-    println!("Emu: Usage: emu <memory image file>");
+  let (args, flags) = common::parse_flags(args);
+  if args.len() < 2 {
+    println!("Emu: Usage: emu <memory image file>... [--link=<socket file>] [--bank-size=<bytes>] [--debug=<debug info file> [--regions]] [--history] [--sanitize[=self-modifying]] [--clock-speed=<hertz>] [--sound=<file>] [--timer] [--phosphor[=<clocks>]] [--key-map=<key map file>] [--restore=<snapshot file>] [--snapshot=<snapshot file>] [--record=<input log file>] [--replay=<input log file>] [--gdb=<port> [--stdin=<file>]] [--batch [--stdin=<file>] [--stdout=<file>] [--clocks=<count>] [--instructions=<count>] [--trace=<file>] [--profile=<file> [--profile-tokens]] [--capture=<file> [--capture-interval=<clocks>] [--capture-scale=<factor>]]]");
     std::process::exit(1);
   }
 
+  // batch and GDB modes hand standard output to the machine or to GDB, so errors go to
+  // `stderr` there instead
+  let batch = ["batch", "gdb"]
+    .iter()
+    .any(|mode| flags.contains_key(*mode));
+  macro_rules! report {
+    ($($arg:tt)*) => {
+      match batch {
+        true => eprintln!($($arg)*),
+        false => println!($($arg)*),
+      }
+    };
+  }
+
   let memory_image_file: &String = &args[1];
 
-  let memory_image: [u8; common::MEM_SIZE] = std::fs::read(memory_image_file)
-    .unwrap_or_else(|_| {
-      println!("Emu: Error: Unable to read file '{}'", memory_image_file);
-      std::process::exit(1);
-    })
-    .try_into()
-    .unwrap_or_else(|_| {
-      println!(
-        "Emu: Error: Memory image '{}' has incorrect size",
-        memory_image_file,
-      );
+  // memory images past the first are loaded into peers linked to the first machine
+  if args.len() > 2 && batch {
+    report!("Emu: Error: Linking several memory images requires interactive mode");
+    std::process::exit(1);
+  }
+  if args.len() > 2 && flags.contains_key("link") {
+    report!("Emu: Error: Flag '--link' cannot be combined with several memory images");
+    std::process::exit(1);
+  }
+
+  let (memory_image, banks) =
+    common::load_memory_image(memory_image_file, &flags).unwrap_or_else(|error| {
+      report!("Emu: Error: {}", error);
       std::process::exit(1);
     });
 
-  let mc = Microcomputer {
-    mem: memory_image,
-    mp: Microprocessor {
-      ip: 0x00,
-      sp: 0x00,
-      cf: false,
-    },
-  };
-
-  common::execute(mc, 1000000);
-}
-
-struct Microcomputer {
-  mem: [u8; common::MEM_SIZE], // memory
-  mp: Microprocessor,          // microprocessor
-}
-
-struct Microprocessor {
-  ip: u8,   // instruction pointer
-  sp: u8,   // stack pointer
-  cf: bool, // carry flag
-}
+  let debug_info = common::load_debug_info(&flags).unwrap_or_else(|error| {
+    report!("Emu: Error: {}", error);
+    std::process::exit(1);
+  });
 
-impl Tickable for Microcomputer {
-  fn reset(
-    &mut self,
-    stdin: &mut VecDeque<u8>,
-    stdout: &mut VecDeque<u8>,
-    display: &mut [u8; common::DISPLAY_BUFFER_LEN],
-    _controller: &mut u8,
-  ) {
-    self.mp.ip = 0x00;
-    self.mp.sp = 0x00;
-    self.mp.cf = false;
-    stdin.clear();
-    stdout.clear();
-    stdin.push_back(self.mem[common::STDIO_BUFFER]);
-    display.copy_from_slice(
-      &self.mem[common::DISPLAY_BUFFER..common::DISPLAY_BUFFER + common::DISPLAY_BUFFER_LEN],
-    );
+  if (flags.contains_key("sound") || flags.contains_key("timer")) && banks.is_some() {
+    report!("Emu: Error: Peripheral registers overlap memory expansion window");
+    std::process::exit(1);
   }
+  let sound = common::create_sound(&flags).unwrap_or_else(|error| {
+    report!("Emu: Error: {}", error);
+    std::process::exit(1);
+  });
+  let timer = common::create_timer(&flags).unwrap_or_else(|error| {
+    report!("Emu: Error: {}", error);
+    std::process::exit(1);
+  });
+  let link = common::create_link(&flags).unwrap_or_else(|error| {
+    report!("Emu: Error: {}", error);
+    std::process::exit(1);
+  });
 
-  fn tick(
-    &mut self,
-    stdin: &mut VecDeque<u8>,
-    stdout: &mut VecDeque<u8>,
-    display: &mut [u8; common::DISPLAY_BUFFER_LEN],
-    controller: &mut u8,
-  ) -> Result<u128, TickTrap> {
-    let mp = &mut self.mp;
-
-    macro_rules! mem_read {
-      ($address:expr) => {{
-        let address = $address as usize;
-        if address == common::STDIO_BUFFER {
-          stdin.pop_front().unwrap_or(*controller)
-        } else {
-          self.mem[address]
-        }
-      }};
-    }
-
-    macro_rules! mem_write {
-      ($address:expr, $value:expr) => {{
-        let address = $address as usize;
-        let value = $value;
-        if address == common::STDIO_BUFFER {
-          stdout.push_back(value);
-        } else {
-          self.mem[address] = value;
-        }
-        if address & common::DISPLAY_BUFFER == common::DISPLAY_BUFFER {
-          display[address & !common::DISPLAY_BUFFER] = value
-        }
-      }};
-    }
-
-    macro_rules! sp_push {
-      ($value:expr) => {{
-        let value = $value;
-        mp.sp = mp.sp.wrapping_sub(1);
-        mem_write!(mp.sp, value);
-      }};
-    }
-
-    macro_rules! sp_pop {
-      () => {{
-        let value = mem_read!(mp.sp);
-        mp.sp = mp.sp.wrapping_add(1);
+  let mut mc = Microcomputer::new(memory_image);
+  if let Some(banks) = banks {
+    mc.bus_mut().attach(Box::new(banks));
+  }
+  if let Some(sound) = sound {
+    mc.bus_mut().attach(Box::new(sound));
+  }
+  if let Some(timer) = &timer {
+    mc.bus_mut().attach(Box::new(timer.clone()));
+  }
+  if let Some(link) = link {
+    mc.bus_mut().attach_front(Box::new(link));
+  }
+  if flags.contains_key("regions") {
+    let Some(debug_info) = &debug_info else {
+      report!("Emu: Error: Flag '--regions' requires flag '--debug'");
+      std::process::exit(1);
+    };
+    mc.set_regions(debug_info.regions.clone());
+  }
+  let sanitizer = match flags.get("sanitize").map(String::as_str) {
+    None => None,
+    Some("") => Some(false),
+    Some("self-modifying") => Some(true),
+    Some(value) => {
+      report!(
+        "Emu: Error: Invalid value '{}' for flag '--sanitize'",
         value
-      }};
+      );
+      std::process::exit(1);
     }
+  };
+  if let Some(self_modifying) = sanitizer {
+    mc.set_sanitizer(self_modifying);
+  }
 
-    let opcode = mem_read!(mp.ip);
-    mp.ip = mp.ip.wrapping_add(1);
-
-    let instruction = common::opcode_to_instruction(opcode).map_err(|_| TickTrap::IllegalOpcode)?;
-
-    match instruction {
-      Instruction::Psh(imm) => {
-        sp_push!(imm.get());
-        Ok(10)
-      }
-
-      Instruction::Add(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let res = (mem_read!(addr) as u16)
-          .wrapping_add(sp_pop!() as u16)
-          .wrapping_add(mp.cf as u16);
-        mem_write!(addr, res as u8);
-        mp.cf = res > 0xFF;
-        Ok(14 + size.get() as u128)
-      }
-
-      Instruction::Sub(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let res = (mem_read!(addr) as u16)
-          .wrapping_sub(sp_pop!() as u16)
-          .wrapping_sub(mp.cf as u16);
-        mem_write!(addr, res as u8);
-        mp.cf = res > 0xFF;
-        Ok(14 + size.get() as u128)
-      }
-
-      Instruction::Iff(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let top = sp_pop!();
-        mem_write!(addr, if mp.cf { top } else { mem_read!(addr) });
-        Ok(13 + size.get() as u128)
-      }
-
-      Instruction::Swp(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let top = sp_pop!();
-        sp_push!(mem_read!(addr));
-        mem_write!(addr, top);
-        Ok(13 + size.get() as u128)
-      }
-
-      Instruction::Rot(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let top = sp_pop!();
-        let shifted = (mem_read!(addr) as u16) << top % 8;
-        let res = (shifted & 0xFF) as u8 | (shifted >> 8) as u8;
-        mem_write!(addr, res);
-        mp.cf = false;
-        Ok((18 + size.get() as u128) * (top as u128 + 1))
-      }
-
-      Instruction::Orr(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let res = sp_pop!() | mem_read!(addr);
-        mem_write!(addr, res);
-        mp.cf = res == 0x00;
-        Ok(14 + size.get() as u128)
-      }
-
-      Instruction::And(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let res = sp_pop!() & mem_read!(addr);
-        mem_write!(addr, res);
-        mp.cf = res == 0x00;
-        Ok(11 + size.get() as u128)
-      }
-
-      Instruction::Xor(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let res = sp_pop!() ^ mem_read!(addr);
-        mem_write!(addr, res);
-        mp.cf = res == 0x00;
-        Ok(22 + size.get() as u128)
-      }
-
-      Instruction::Xnd(size) => {
-        let addr = mp.sp.wrapping_add(size.get());
-        let res = sp_pop!() & 0x00;
-        mem_write!(addr, res);
-        mp.cf = res == 0x00;
-        Ok(8 + size.get() as u128)
-      }
-
-      Instruction::Inc => {
-        sp_push!(sp_pop!().wrapping_add(1));
-        Ok(6)
-      }
-
-      Instruction::Dec => {
-        sp_push!(sp_pop!().wrapping_sub(1));
-        Ok(8)
-      }
-
-      Instruction::Neg => {
-        sp_push!(sp_pop!().wrapping_neg());
-        Ok(11)
-      }
-
-      Instruction::Shl => {
-        let top = sp_pop!();
-        sp_push!(top.wrapping_shl(1) | (mp.cf as u8));
-        mp.cf = top & 0b10000000 != 0x00;
-        Ok(9)
-      }
-
-      Instruction::Shr => {
-        let top = sp_pop!();
-        sp_push!(top.wrapping_shr(1) | (mp.cf as u8) << 7);
-        mp.cf = top & 0b00000001 != 0x00;
-        Ok(16)
-      }
-
-      Instruction::Not => {
-        let res = !sp_pop!();
-        sp_push!(res);
-        mp.cf = res == 0x00;
-        Ok(8)
-      }
-
-      Instruction::Buf => {
-        let res = sp_pop!();
-        sp_push!(res);
-        mp.cf = res == 0x00;
-        Ok(9)
-      }
-
-      Instruction::Dbg => Err(TickTrap::DebugRequest),
-
-      Instruction::Ldo(ofst) => {
-        let addr = mp.sp.wrapping_add(ofst.get());
-        sp_push!(mem_read!(addr));
-        Ok(12 + ofst.get() as u128)
-      }
-
-      Instruction::Sto(ofst) => {
-        let top = sp_pop!();
-        let addr = mp.sp.wrapping_add(ofst.get());
-        mem_write!(addr, top);
-        Ok(11 + ofst.get() as u128)
-      }
-
-      Instruction::Lda => {
-        sp_push!(mem_read!(sp_pop!()));
-        Ok(9)
-      }
-
-      Instruction::Sta => {
-        mem_write!(sp_pop!(), sp_pop!());
-        Ok(15)
-      }
-
-      Instruction::Ldi => {
-        sp_push!(mp.ip);
-        Ok(9)
-      }
-
-      Instruction::Sti => {
-        mp.ip = sp_pop!();
-        Ok(6)
-      }
-
-      Instruction::Lds => {
-        sp_push!(mp.sp);
-        Ok(10)
-      }
-
-      Instruction::Sts => {
-        mp.sp = sp_pop!();
-        Ok(5)
+  let mut peers: Vec<Microcomputer> = (args[2..].iter())
+    .map(|memory_image_file| {
+      let (memory_image, banks) = common::load_memory_image(memory_image_file, &flags)
+        .unwrap_or_else(|error| {
+          report!("Emu: Error: {}", error);
+          std::process::exit(1);
+        });
+      if flags.contains_key("timer") && banks.is_some() {
+        report!("Emu: Error: Peripheral registers overlap memory expansion window");
+        std::process::exit(1);
       }
-
-      Instruction::Clc => {
-        mp.cf = false;
-        Ok(6)
+      let mut peer = Microcomputer::new(memory_image);
+      if let Some(banks) = banks {
+        peer.bus_mut().attach(Box::new(banks));
       }
-
-      Instruction::Sec => {
-        mp.cf = true;
-        Ok(6)
+      if let Some(timer) = &timer {
+        peer.bus_mut().attach(Box::new(timer.clone()));
       }
-
-      Instruction::Flc => {
-        mp.cf = !mp.cf;
-        Ok(6)
-      }
-
-      Instruction::Nop => Ok(3),
-
-      Instruction::Pop => {
-        mp.sp = mp.sp.wrapping_add(1);
-        Ok(5)
-      }
-
-      Instruction::Phn(nimm) => {
-        sp_push!(nimm.get());
-        Ok(10)
+      if let Some(self_modifying) = sanitizer {
+        peer.set_sanitizer(self_modifying);
       }
+      peer
+    })
+    .collect();
+  if !peers.is_empty() {
+    let mut serials = common::Serial::mesh(peers.len() + 1).into_iter();
+    mc.bus_mut().attach_front(Box::new(serials.next().unwrap()));
+    for (peer, serial) in peers.iter_mut().zip(serials) {
+      peer.bus_mut().attach_front(Box::new(serial));
     }
   }
-}
 
-impl std::fmt::Display for Microcomputer {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(
-      f,
-      "{}\r\n{}",
-      self.mp,
-      common::render_memory(&self.mem, self.mp.ip, self.mp.sp, self.mp.cf),
-    )
-  }
-}
-
-impl std::fmt::Display for Microprocessor {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(
-      f,
-      "IP  SP  CF\r\n{:02X}  {:02X}  {:01X} \r\n",
-      self.ip, self.sp, self.cf as u8,
-    )
+  let snapshot = common::load_snapshot(&flags, &mc).unwrap_or_else(|error| {
+    report!("Emu: Error: {}", error);
+    std::process::exit(1);
+  });
+
+  match (flags.contains_key("batch"), flags.get("gdb")) {
+    (false, None) => {
+      let options = common::parse_interactive(&flags, memory_image_file).unwrap_or_else(|error| {
+        println!("Emu: Error: {}", error);
+        std::process::exit(1);
+      });
+      common::execute(mc, peers, options, snapshot).unwrap_or_else(|error| {
+        println!("Emu: Error: {}", error);
+        std::process::exit(1);
+      });
+    }
+    (false, Some(target)) => {
+      let stdin = common::parse_stdin(&flags).unwrap_or_else(|error| {
+        eprintln!("Emu: Error: {}", error);
+        std::process::exit(1);
+      });
+      common::execute_gdb("Emu", mc, target, stdin, snapshot).unwrap_or_else(|error| {
+        eprintln!("Emu: Error: {}", error);
+        std::process::exit(1);
+      });
+    }
+    (true, _) => {
+      let batch = common::parse_batch(&flags).unwrap_or_else(|error| {
+        eprintln!("Emu: Error: {}", error);
+        std::process::exit(1);
+      });
+      let (stop, clocks) = common::execute_batch(mc, batch, snapshot).unwrap_or_else(|error| {
+        eprintln!("Emu: Error: {}", error);
+        std::process::exit(1);
+      });
+      std::process::exit(common::report_batch("Emu", stop, clocks));
+    }
   }
 }
@@ -0,0 +1,705 @@
+use crate::common;
+use crate::common::*;
+use std::collections::VecDeque;
+
+pub struct Microcomputer {
+  mem: [u8; common::MEM_SIZE], // memory
+  mp: Microprocessor,          // microprocessor
+  bus: Bus,                    // memory-mapped peripherals
+
+  fixed_point: Option<(u8, u8, bool)>, // IP, SP and CF after last `sti`
+  side_effect: bool,                   // peripheral state changed since last `sti`
+  dirty: Vec<(u8, u8)>,                // bytes written since last `sti` and their value back then
+  halted: bool,                        // last `sti` reached a fixed point
+  accesses: Vec<Access>,               // memory accesses during last tick
+  before: (u8, u8, u8, bool, [u8; 4]), // IP, opcode, SP, CF and stack before last tick
+  regions: Vec<(u8, u8, Region)>,      // memory region map to enforce, if any
+  shadow: Option<Shadow>,              // shadow memory for the sanitizer, if enabled
+}
+
+struct Shadow {
+  written: [bool; common::MEM_SIZE], // written by the program rather than the image
+  undefined: [bool; common::MEM_SIZE], // popped off the stack and not written since
+  executed: [bool; common::MEM_SIZE], // fetched as an opcode
+  self_modifying: bool,              // program opted in to self-modification
+  reported: Vec<(u8, TickTrap)>,     // instruction and trap of every finding so far
+}
+
+// findings of the sanitizer, in the order they are saved in machine state
+const SHADOW_TRAPS: [TickTrap; 3] = [
+  TickTrap::UninitializedRead,
+  TickTrap::CodeModification,
+  TickTrap::DataExecution,
+];
+const SHADOW_STATE_LEN: usize = 1 + (3 + SHADOW_TRAPS.len()) * common::MEM_SIZE / 8;
+
+struct Microprocessor {
+  ip: u8,   // instruction pointer
+  sp: u8,   // stack pointer
+  cf: bool, // carry flag
+}
+
+impl Microcomputer {
+  pub fn new(memory_image: [u8; common::MEM_SIZE]) -> Microcomputer {
+    Microcomputer {
+      mem: memory_image,
+      mp: Microprocessor {
+        ip: 0x00,
+        sp: 0x00,
+        cf: false,
+      },
+      bus: Bus::default(),
+      fixed_point: None,
+      side_effect: false,
+      dirty: vec![],
+      halted: false,
+      accesses: vec![],
+      before: (0x00, 0x00, 0x00, false, [0x00; 4]),
+      regions: vec![],
+      shadow: None,
+    }
+  }
+
+  pub fn set_regions(&mut self, regions: Vec<(u8, u8, Region)>) {
+    // trap on writes into `code` regions and, if any `stack` region is given,
+    // on SP leaving every `stack` region. an SP one past the end of a region
+    // is within it, as it denotes an empty stack
+    self.regions = regions;
+  }
+
+  pub fn set_sanitizer(&mut self, self_modifying: bool) {
+    // trap on reads of stack slots popped and never written since, on `sta` into
+    // bytes already executed and on execution of bytes written by the program.
+    // the latter two are allowed for self-modifying programs. every finding is
+    // only reported once per instruction so execution can be continued past it
+    self.shadow = Some(Shadow {
+      written: [false; common::MEM_SIZE],
+      undefined: [false; common::MEM_SIZE],
+      executed: [false; common::MEM_SIZE],
+      self_modifying,
+      reported: vec![],
+    });
+  }
+
+  fn step(&mut self, io: &mut Io) -> Result<u128, TickTrap> {
+    // execute one instruction
+    let mp = &mut self.mp;
+
+    macro_rules! mem_read {
+      ($address:expr) => {{
+        let address = $address as usize;
+        self.accesses.push(Access::Read(address as u8));
+        match self.bus.read(address as u8, io) {
+          Some(value) => {
+            self.side_effect |= self.bus.has_side_effects(address as u8);
+            value
+          }
+          None => self.mem[address],
+        }
+      }};
+    }
+
+    macro_rules! mem_write {
+      ($address:expr, $value:expr) => {{
+        let address = $address as usize;
+        let value = $value;
+        self.accesses.push(Access::Write(address as u8));
+        let previous = self.bus.peek(address as u8, io);
+        if self.bus.write(address as u8, value, io) {
+          self.side_effect |= self.bus.has_side_effects(address as u8) || previous != Some(value);
+        } else {
+          if !self
+            .dirty
+            .iter()
+            .any(|(dirty, _)| *dirty as usize == address)
+          {
+            self.dirty.push((address as u8, self.mem[address]));
+          }
+          self.mem[address] = value;
+        }
+      }};
+    }
+
+    macro_rules! sp_push {
+      ($value:expr) => {{
+        let value = $value;
+        mp.sp = mp.sp.wrapping_sub(1);
+        mem_write!(mp.sp, value);
+      }};
+    }
+
+    macro_rules! sp_pop {
+      () => {{
+        let value = mem_read!(mp.sp);
+        mp.sp = mp.sp.wrapping_add(1);
+        value
+      }};
+    }
+
+    let opcode = mem_read!(mp.ip);
+    mp.ip = mp.ip.wrapping_add(1);
+
+    let instruction = common::opcode_to_instruction(opcode).map_err(|_| TickTrap::IllegalOpcode)?;
+
+    match instruction {
+      Instruction::Psh(imm) => {
+        sp_push!(imm.get());
+        Ok(10)
+      }
+
+      Instruction::Add(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let res = (mem_read!(addr) as u16)
+          .wrapping_add(sp_pop!() as u16)
+          .wrapping_add(mp.cf as u16);
+        mem_write!(addr, res as u8);
+        mp.cf = res > 0xFF;
+        Ok(14 + size.get() as u128)
+      }
+
+      Instruction::Sub(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let res = (mem_read!(addr) as u16)
+          .wrapping_sub(sp_pop!() as u16)
+          .wrapping_sub(mp.cf as u16);
+        mem_write!(addr, res as u8);
+        mp.cf = res > 0xFF;
+        Ok(14 + size.get() as u128)
+      }
+
+      Instruction::Iff(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let top = sp_pop!();
+        mem_write!(addr, if mp.cf { top } else { mem_read!(addr) });
+        Ok(13 + size.get() as u128)
+      }
+
+      Instruction::Swp(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let top = sp_pop!();
+        sp_push!(mem_read!(addr));
+        mem_write!(addr, top);
+        Ok(13 + size.get() as u128)
+      }
+
+      Instruction::Rot(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let top = sp_pop!();
+        let shifted = (mem_read!(addr) as u16) << (top % 8);
+        let res = (shifted & 0xFF) as u8 | (shifted >> 8) as u8;
+        mem_write!(addr, res);
+        // the microcode counts the shift amount down to zero in place before popping it
+        if top != 0x00 {
+          mem_write!(mp.sp.wrapping_sub(1), 0x00);
+        }
+        mp.cf = false;
+        Ok((18 + size.get() as u128) * (top as u128 + 1))
+      }
+
+      Instruction::Orr(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let res = sp_pop!() | mem_read!(addr);
+        mem_write!(addr, res);
+        mp.cf = res == 0x00;
+        Ok(14 + size.get() as u128)
+      }
+
+      Instruction::And(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let res = sp_pop!() & mem_read!(addr);
+        mem_write!(addr, res);
+        mp.cf = res == 0x00;
+        Ok(11 + size.get() as u128)
+      }
+
+      Instruction::Xor(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        let res = sp_pop!() ^ mem_read!(addr);
+        mem_write!(addr, res);
+        mp.cf = res == 0x00;
+        Ok(22 + size.get() as u128)
+      }
+
+      Instruction::Xnd(size) => {
+        let addr = mp.sp.wrapping_add(size.get());
+        sp_pop!(); // `*SP & 0x00` is read all the same
+        let res = 0x00;
+        mem_write!(addr, res);
+        mp.cf = res == 0x00;
+        Ok(8 + size.get() as u128)
+      }
+
+      Instruction::Inc => {
+        sp_push!(sp_pop!().wrapping_add(1));
+        Ok(6)
+      }
+
+      Instruction::Dec => {
+        sp_push!(sp_pop!().wrapping_sub(1));
+        Ok(8)
+      }
+
+      Instruction::Neg => {
+        sp_push!(sp_pop!().wrapping_neg());
+        Ok(11)
+      }
+
+      Instruction::Shl => {
+        let top = sp_pop!();
+        sp_push!(top.wrapping_shl(1) | (mp.cf as u8));
+        mp.cf = top & 0b10000000 != 0x00;
+        Ok(9)
+      }
+
+      Instruction::Shr => {
+        let top = sp_pop!();
+        sp_push!(top.wrapping_shr(1) | (mp.cf as u8) << 7);
+        mp.cf = top & 0b00000001 != 0x00;
+        Ok(16)
+      }
+
+      Instruction::Not => {
+        let res = !sp_pop!();
+        sp_push!(res);
+        mp.cf = res == 0x00;
+        Ok(8)
+      }
+
+      Instruction::Buf => {
+        let res = sp_pop!();
+        sp_push!(res);
+        mp.cf = res == 0x00;
+        Ok(9)
+      }
+
+      Instruction::Dbg => Err(TickTrap::DebugRequest),
+
+      Instruction::Ldo(ofst) => {
+        let addr = mp.sp.wrapping_add(ofst.get());
+        sp_push!(mem_read!(addr));
+        Ok(12 + ofst.get() as u128)
+      }
+
+      Instruction::Sto(ofst) => {
+        let top = sp_pop!();
+        let addr = mp.sp.wrapping_add(ofst.get());
+        mem_write!(addr, top);
+        Ok(11 + ofst.get() as u128)
+      }
+
+      Instruction::Lda => {
+        sp_push!(mem_read!(sp_pop!()));
+        Ok(9)
+      }
+
+      Instruction::Sta => {
+        mem_write!(sp_pop!(), sp_pop!());
+        Ok(15)
+      }
+
+      Instruction::Ldi => {
+        sp_push!(mp.ip);
+        Ok(9)
+      }
+
+      Instruction::Sti => {
+        mp.ip = sp_pop!();
+        // jumping back to an identical state without side effects means looping forever. bytes
+        // written over and then restored, as by the pushes of a jump, leave the state identical
+        let state = (mp.ip, mp.sp, mp.cf);
+        let changed =
+          (self.dirty.iter()).any(|(address, value)| self.mem[*address as usize] != *value);
+        self.halted = !self.side_effect && !changed && self.fixed_point == Some(state);
+        self.fixed_point = Some(state);
+        self.side_effect = false;
+        self.dirty.clear();
+        Ok(6)
+      }
+
+      Instruction::Lds => {
+        sp_push!(mp.sp);
+        Ok(10)
+      }
+
+      Instruction::Sts => {
+        mp.sp = sp_pop!();
+        Ok(5)
+      }
+
+      Instruction::Clc => {
+        mp.cf = false;
+        Ok(6)
+      }
+
+      Instruction::Sec => {
+        mp.cf = true;
+        Ok(6)
+      }
+
+      Instruction::Flc => {
+        mp.cf = !mp.cf;
+        Ok(6)
+      }
+
+      Instruction::Nop => Ok(3),
+
+      Instruction::Pop => {
+        mp.sp = mp.sp.wrapping_add(1);
+        Ok(5)
+      }
+
+      Instruction::Phn(nimm) => {
+        sp_push!(nimm.get());
+        Ok(10)
+      }
+    }
+  }
+
+  fn check_regions(&self) -> Result<(), TickTrap> {
+    let within = |address: u8, region: Region, one_past: bool| {
+      self.regions.iter().any(|(first, last, r)| {
+        *r == region
+          && ((*first..=*last).contains(&address) || (one_past && address == last.wrapping_add(1)))
+      })
+    };
+
+    let collision = self.accesses.iter().any(|access| match access {
+      Access::Write(address) => {
+        *address as usize != common::STDIO_BUFFER && within(*address, Region::Code, false)
+      }
+      Access::Read(_) => false,
+    });
+    if collision {
+      return Err(TickTrap::CodeCollision);
+    }
+
+    let stack = self.regions.iter().any(|(_, _, r)| *r == Region::Stack);
+    if stack && !within(self.mp.sp, Region::Stack, true) {
+      return Err(TickTrap::StackOverflow);
+    }
+
+    Ok(())
+  }
+
+  fn check_shadow(&mut self) -> Result<(), TickTrap> {
+    let Some(shadow) = &mut self.shadow else {
+      return Ok(());
+    };
+    let (ip, opcode, sp, _, _) = self.before;
+    let instruction = common::opcode_to_instruction(opcode);
+
+    // every read but the opcode fetch and the target of `lda` is from the stack.
+    // stack pushes over executed code are left to the memory region map, as
+    // programs commonly reuse their startup code as stack space
+    let (mut stack_reads, mut writes) = (vec![], vec![]);
+    for access in self.accesses.iter().skip(1) {
+      match access {
+        Access::Read(address) => stack_reads.push(*address as usize),
+        Access::Write(address) => writes.push(*address as usize),
+      }
+    }
+    if let Ok(Instruction::Lda) = instruction {
+      stack_reads.pop();
+    }
+    // the shift amount `rot` counts down is popped off the stack all the same
+    if let Ok(Instruction::Rot(_)) = instruction {
+      writes.retain(|address| *address != sp as usize);
+    }
+    // `STDIO_BUFFER` is never shadowed as it does not behave like memory
+    stack_reads.retain(|address| *address != common::STDIO_BUFFER);
+    writes.retain(|address| *address != common::STDIO_BUFFER);
+    let pointer_writes = match instruction {
+      Ok(Instruction::Sta) => &writes[..],
+      _ => &[],
+    };
+
+    let tick_trap = if !shadow.self_modifying && shadow.written[ip as usize] {
+      Some(TickTrap::DataExecution)
+    } else if stack_reads.iter().any(|address| shadow.undefined[*address]) {
+      Some(TickTrap::UninitializedRead)
+    } else if !shadow.self_modifying
+      && pointer_writes
+        .iter()
+        .any(|address| shadow.executed[*address])
+    {
+      Some(TickTrap::CodeModification)
+    } else {
+      None
+    };
+
+    match tick_trap {
+      Some(tick_trap) if !shadow.reported.contains(&(ip, tick_trap)) => {
+        shadow.reported.push((ip, tick_trap));
+        Err(tick_trap)
+      }
+      _ => {
+        shadow.executed[ip as usize] = true;
+        // slots popped off the stack are undefined. `sts` switches stacks instead
+        if !matches!(instruction, Ok(Instruction::Sts)) {
+          let popped = self.mp.sp.wrapping_sub(sp);
+          if popped < 0x80 {
+            (0..popped)
+              .for_each(|offset| shadow.undefined[sp.wrapping_add(offset) as usize] = true);
+          }
+        }
+        writes.iter().for_each(|address| {
+          shadow.written[*address] = true;
+          shadow.undefined[*address] = false;
+        });
+        Ok(())
+      }
+    }
+  }
+}
+
+impl Tickable for Microcomputer {
+  fn reset(
+    &mut self,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; common::DISPLAY_BUFFER_LEN],
+    controller: &mut u8,
+  ) {
+    self.mp.ip = 0x00;
+    self.mp.sp = 0x00;
+    self.mp.cf = false;
+    self.fixed_point = None;
+    self.dirty.clear();
+    self.halted = false;
+    if let Some(shadow) = &mut self.shadow {
+      shadow.written = [false; common::MEM_SIZE];
+      shadow.undefined = [false; common::MEM_SIZE];
+      shadow.executed = [false; common::MEM_SIZE];
+      shadow.reported.clear();
+    }
+    let mut io = Io {
+      stdin,
+      stdout,
+      display,
+      controller,
+    };
+    self.bus.reset(&self.mem, &mut io);
+  }
+
+  fn tick(
+    &mut self,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; common::DISPLAY_BUFFER_LEN],
+    controller: &mut u8,
+  ) -> Result<u128, TickTrap> {
+    // trap after the `sti` so its clocks are accounted for, as in `sim`
+    if self.halted {
+      return Err(TickTrap::Halted);
+    }
+
+    self.accesses.clear();
+    self.before = (
+      self.mp.ip,
+      self.mem[self.mp.ip as usize],
+      self.mp.sp,
+      self.mp.cf,
+      std::array::from_fn(|offset| self.mem[self.mp.sp.wrapping_add(offset as u8) as usize]),
+    );
+
+    let mut io = Io {
+      stdin,
+      stdout,
+      display,
+      controller,
+    };
+
+    if self.regions.is_empty() && self.shadow.is_none() {
+      let clocks = self.step(&mut io)?;
+      self.bus.tick(clocks, &mut io);
+      return Ok(clocks);
+    }
+
+    let state = self.save();
+    let stdin_front: Vec<u8> = io.stdin.iter().take(4).copied().collect();
+    let (stdin_len, stdout_len) = (io.stdin.len(), io.stdout.len());
+
+    let clocks = self.step(&mut io)?;
+    let checked = self.check_regions().and_then(|_| self.check_shadow());
+    checked.inspect_err(|_| {
+      // roll back so the offending instruction is the one about to be executed.
+      // stdio and the display live outside of the machine so are rolled back by hand
+      self.restore(&state);
+      let consumed = stdin_len.saturating_sub(io.stdin.len());
+      stdin_front
+        .iter()
+        .take(consumed)
+        .rev()
+        .for_each(|c| io.stdin.push_front(*c));
+      io.stdout.truncate(stdout_len);
+      io.display.copy_from_slice(
+        &self.mem[common::DISPLAY_BUFFER..common::DISPLAY_BUFFER + common::DISPLAY_BUFFER_LEN],
+      );
+    })?;
+
+    self.bus.tick(clocks, &mut io);
+    Ok(clocks)
+  }
+
+  fn boundary(&self) -> bool {
+    true // every tick executes exactly one instruction
+  }
+
+  fn accesses(&self) -> &[Access] {
+    &self.accesses
+  }
+
+  fn ip(&self) -> u8 {
+    self.mp.ip
+  }
+
+  fn sp(&self) -> u8 {
+    self.mp.sp
+  }
+
+  fn cf(&self) -> bool {
+    self.mp.cf
+  }
+
+  fn mem(&self) -> &[u8; common::MEM_SIZE] {
+    &self.mem
+  }
+
+  fn set_ip(&mut self, ip: u8) {
+    self.mp.ip = ip;
+  }
+
+  fn set_sp(&mut self, sp: u8) {
+    self.mp.sp = sp;
+  }
+
+  fn set_cf(&mut self, cf: bool) {
+    self.mp.cf = cf;
+  }
+
+  fn mem_mut(&mut self) -> &mut [u8; common::MEM_SIZE] {
+    &mut self.mem
+  }
+
+  fn trace(&self) -> Option<String> {
+    let (ip, opcode, sp, cf, stack) = self.before;
+    let mnemonic = common::token_to_mnemonic(common::instruction_to_token(
+      common::opcode_to_instruction(opcode),
+    ));
+    let stack = stack
+      .iter()
+      .map(|value| format!("{:02X}", value))
+      .collect::<Vec<String>>()
+      .join(" ");
+    Some(format!(
+      "{:02X} {:02X} {} {:02X} {:01X} {}",
+      ip, opcode, mnemonic, sp, cf as u8, stack
+    ))
+  }
+
+  fn save(&self) -> Vec<u8> {
+    let fixed_point = self.fixed_point.unwrap_or_default();
+    let mut state = self.mem.to_vec();
+    state.extend([self.mp.ip, self.mp.sp, self.mp.cf as u8]);
+    state.extend([
+      self.fixed_point.is_some() as u8,
+      fixed_point.0,
+      fixed_point.1,
+      fixed_point.2 as u8,
+    ]);
+    state.extend([self.side_effect as u8, self.halted as u8]);
+    state.extend(common::save_dirty(&self.dirty));
+    state.extend(save_shadow(self.shadow.as_ref()));
+    state.extend(self.bus.save());
+    state
+  }
+
+  fn restore(&mut self, state: &[u8]) {
+    let (mem, state) = state.split_at(common::MEM_SIZE);
+    let (state, rest) = state.split_at(9);
+    let (dirty, rest) = rest.split_at(common::DIRTY_STATE_LEN);
+    let (shadow, bus) = rest.split_at(SHADOW_STATE_LEN);
+    self.dirty = common::restore_dirty(dirty);
+    if let Some(self_shadow) = &mut self.shadow {
+      restore_shadow(self_shadow, shadow);
+    }
+    self.mem.copy_from_slice(mem);
+    self.bus.restore(bus);
+    let [ip, sp, cf, fixed, fixed_ip, fixed_sp, fixed_cf, side_effect, halted] =
+      state.try_into().unwrap();
+    self.mp.ip = ip;
+    self.mp.sp = sp;
+    self.mp.cf = cf != 0x00;
+    self.fixed_point = (fixed != 0x00).then_some((fixed_ip, fixed_sp, fixed_cf != 0x00));
+    self.side_effect = side_effect != 0x00;
+    self.halted = halted != 0x00;
+  }
+
+  fn bus(&self) -> &Bus {
+    &self.bus
+  }
+
+  fn bus_mut(&mut self) -> &mut Bus {
+    &mut self.bus
+  }
+}
+
+fn save_shadow(shadow: Option<&Shadow>) -> Vec<u8> {
+  // whether the sanitizer was enabled, then every shadow flag and every finding reported so
+  // far as fixed-size bit masks so machine state keeps the same size throughout
+  let Some(shadow) = shadow else {
+    return vec![0x00; SHADOW_STATE_LEN];
+  };
+  let mut state = vec![0x01];
+  state.extend(common::pack_bits(&shadow.written));
+  state.extend(common::pack_bits(&shadow.undefined));
+  state.extend(common::pack_bits(&shadow.executed));
+  for tick_trap in SHADOW_TRAPS {
+    let mut reported = [false; common::MEM_SIZE];
+    (shadow.reported.iter())
+      .filter(|(_, trap)| *trap == tick_trap)
+      .for_each(|(ip, _)| reported[*ip as usize] = true);
+    state.extend(common::pack_bits(&reported));
+  }
+  state
+}
+
+fn restore_shadow(shadow: &mut Shadow, state: &[u8]) {
+  // state saved without the sanitizer enabled restores a clean shadow
+  let (enabled, state) = state.split_first().unwrap();
+  let mut masks = state
+    .chunks(common::MEM_SIZE / 8)
+    .map(|mask| <[bool; common::MEM_SIZE]>::try_from(common::unpack_bits(mask)).unwrap())
+    .map(|mask| mask.map(|bit| bit && *enabled != 0x00));
+  shadow.written = masks.next().unwrap();
+  shadow.undefined = masks.next().unwrap();
+  shadow.executed = masks.next().unwrap();
+  shadow.reported = (SHADOW_TRAPS.iter().zip(masks))
+    .flat_map(|(tick_trap, reported)| {
+      (0..common::MEM_SIZE)
+        .filter(move |ip| reported[*ip])
+        .map(move |ip| (ip as u8, *tick_trap))
+    })
+    .collect();
+}
+
+impl std::fmt::Display for Microcomputer {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{}\r\n{}",
+      self.mp,
+      common::render_memory(&self.mem, self.mp.ip, self.mp.sp, self.mp.cf),
+    )
+  }
+}
+
+impl std::fmt::Display for Microprocessor {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "IP  SP  CF\r\n{:02X}  {:02X}  {:01X} \r\n",
+      self.ip, self.sp, self.cf as u8,
+    )
+  }
+}
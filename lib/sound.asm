@@ -0,0 +1,14 @@
+sound_tone! xDE @const
+sound_control! xDF @const
+
+# tone register values for the fourth octave at 1 MHz, as `tone = 1000000 / (0x20 * frequency) - 1`
+note.c! x76 @const
+note.d! x69 @const
+note.e! x5E @const
+note.f! x58 @const
+note.g! x4F @const
+note.a! x46 @const
+note.b! x3E @const
+
+tone! !sound_tone sta x01 !sound_control sta # tone(half_period)
+mute! x00 !sound_control sta # mute()
@@ -0,0 +1,13 @@
+timer_ticks! xDB @const
+timer_clocks! xDC @const
+timer_clocks.hi! xDD @const
+
+tick_rate! x3C @const # ticks per second
+
+ticks! !timer_ticks lda # u8 = ticks()
+# block until the tick register next changes, for pacing frames at `tick_rate`
+wait_tick! !ticks wait_tick. !ticks ld1 !eq .wait_tick !bcs pop # wait_tick()
+# restart the elapsed-clock counter
+clocks.reset! x00 !timer_clocks sta # clocks.reset()
+# the low byte must be read first, as doing so latches the high byte
+clocks! !timer_clocks lda !timer_clocks.hi lda # u16 = clocks()
@@ -1,6 +1,5 @@
-#[path = "../misc/common/common.rs"]
-mod common;
-use common::*;
+use atto8::common;
+use atto8::common::*;
 
 // major parts of this file are identical to /bf/bf-mic.rs
 
@@ -139,7 +138,6 @@ fn build_microcode(errors: &mut impl Extend<Error>) -> [u16; common::MIC_SIZE] {
           rest
             .iter()
             .enumerate()
-            .map(|(step, rest)| (step as usize, rest))
             .map(|(step, rest)| {
               let () = rest;
               let seq = match common::opcode_to_instruction(opcode) {
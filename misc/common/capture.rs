@@ -0,0 +1,342 @@
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureFormat {
+  Pbm,
+  Pgm,
+  Png,
+  Gif,
+}
+
+pub struct Capture {
+  pub file: String,
+  pub format: CaptureFormat,
+  pub interval: Option<u128>, // capture every `interval` clocks instead of on every change
+  pub scale: usize,
+  pub clock_speed: u128, // clocks per second for animation timing
+  pub frames: Vec<(u128, Levels)>,
+}
+
+pub fn parse_capture(flags: &BTreeMap<String, String>) -> Result<Option<Capture>, Error> {
+  let Some(file) = flags.get("capture") else {
+    return Ok(None);
+  };
+
+  let format = match file.rsplit_once('.').map(|(_, extension)| extension) {
+    Some("pbm") => CaptureFormat::Pbm,
+    Some("pgm") => CaptureFormat::Pgm,
+    Some("png") => CaptureFormat::Png,
+    Some("gif") => CaptureFormat::Gif,
+    _ => Err(Error(format!(
+      "Capture file '{}' must end in '.pbm', '.pgm', '.png' or '.gif'",
+      file
+    )))?,
+  };
+  let clock_speed = parse_nominal_clock_speed(flags)?;
+  // a phosphor display changes on every clock, so it is captured once per frame by default
+  let interval = match (
+    parse_count(flags, "capture-interval")?,
+    flags.contains_key("phosphor"),
+  ) {
+    (None, true) => Some((clock_speed / 60).max(1)),
+    (interval, _) => interval,
+  };
+  let scale = parse_count(flags, "capture-scale")?.unwrap_or(1);
+  if interval == Some(0) || scale == 0 || scale > 0x100 {
+    Err(Error("Invalid capture interval or scale".to_string()))?;
+  }
+
+  Ok(Some(Capture {
+    file: file.clone(),
+    format,
+    interval,
+    scale: scale as usize,
+    clock_speed,
+    frames: vec![],
+  }))
+}
+
+impl Capture {
+  pub fn frame(
+    &mut self,
+    clocks: u128,
+    display: &[u8; DISPLAY_BUFFER_LEN],
+    mut phosphor: Option<&mut Phosphor>,
+  ) {
+    let mut levels = |clocks: u128| match &mut phosphor {
+      Some(phosphor) => phosphor.levels(clocks),
+      None => display_levels(display),
+    };
+
+    match self.interval {
+      Some(interval) => {
+        // one frame per interval boundary crossed, even if the display is unchanged
+        let mut next = match self.frames.last() {
+          Some((last, _)) => last + interval,
+          None => 0,
+        };
+        while next <= clocks {
+          self.frames.push((next, levels(next)));
+          next += interval;
+        }
+      }
+      None => {
+        let levels = levels(clocks);
+        if self.frames.last().is_none_or(|(_, last)| *last != levels) {
+          self.frames.push((clocks, levels));
+        }
+      }
+    }
+  }
+
+  pub fn save(&self, clocks: u128) -> Result<(), Error> {
+    let write = |file: &str, bytes: Vec<u8>| {
+      std::fs::write(file, bytes).map_err(|_| Error(format!("Unable to write file '{}'", file)))
+    };
+
+    match self.format {
+      CaptureFormat::Gif => write(&self.file, self.encode_gif(clocks)),
+      CaptureFormat::Pbm | CaptureFormat::Pgm | CaptureFormat::Png => {
+        // `frame.png` becomes `frame-00000.png`, `frame-00001.png` and so on
+        let (stem, extension) = self.file.rsplit_once('.').unwrap();
+        for (index, (_, levels)) in self.frames.iter().enumerate() {
+          let bytes = match self.format {
+            CaptureFormat::Pbm => self.encode_pbm(levels),
+            CaptureFormat::Pgm => self.encode_pgm(levels),
+            _ => self.encode_png(levels),
+          };
+          write(&format!("{}-{:05}.{}", stem, index, extension), bytes)?;
+        }
+        Ok(())
+      }
+    }
+  }
+
+  fn size(&self) -> usize {
+    0x10 * self.scale
+  }
+
+  fn pixels(&self, levels: &Levels) -> Vec<Vec<u8>> {
+    (0..self.size())
+      .map(|y| {
+        (0..self.size())
+          .map(|x| levels[y / self.scale * 0x10 + x / self.scale])
+          .collect()
+      })
+      .collect()
+  }
+
+  fn pack_rows(&self, levels: &Levels, lit: bool) -> Vec<Vec<u8>> {
+    // one bit per pixel, most significant bit first, rows padded to a whole byte.
+    // pixels at least half lit count as lit
+    self
+      .pixels(levels)
+      .iter()
+      .map(|row| {
+        row
+          .chunks(8)
+          .map(|chunk| {
+            chunk.iter().enumerate().fold(0x00, |acc, (index, pixel)| {
+              acc | (((*pixel >= 0x80) == lit) as u8) << (0x07 - index)
+            })
+          })
+          .collect()
+      })
+      .collect()
+  }
+
+  fn encode_pbm(&self, levels: &Levels) -> Vec<u8> {
+    // https://netpbm.sourceforge.net/doc/pbm.html
+    // ones are black, so lit pixels are zeros
+    let mut bytes = format!("P4\n{} {}\n", self.size(), self.size()).into_bytes();
+    bytes.extend(self.pack_rows(levels, false).concat());
+    bytes
+  }
+
+  fn encode_pgm(&self, levels: &Levels) -> Vec<u8> {
+    // https://netpbm.sourceforge.net/doc/pgm.html
+    let mut bytes = format!("P5\n{} {}\n255\n", self.size(), self.size()).into_bytes();
+    bytes.extend(self.pixels(levels).concat());
+    bytes
+  }
+
+  fn encode_png(&self, levels: &Levels) -> Vec<u8> {
+    // https://www.w3.org/TR/png/
+    // 1-bit grayscale, or 8-bit grayscale if any pixel is partially lit, filter type 0 on every
+    // row, deflated with stored blocks only
+    fn crc32(bytes: &[u8]) -> u32 {
+      !bytes.iter().fold(!0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+          (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg())
+        })
+      })
+    }
+
+    fn adler32(bytes: &[u8]) -> u32 {
+      let (a, b) = bytes.iter().fold((1, 0), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+      });
+      b << 16 | a
+    }
+
+    fn chunk(r#type: &[u8], data: &[u8]) -> Vec<u8> {
+      let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+      let body = [r#type, data].concat();
+      chunk.extend(&body);
+      chunk.extend(crc32(&body).to_be_bytes());
+      chunk
+    }
+
+    let (rows, depth) = match is_gray(levels) {
+      true => (self.pixels(levels), 0x08),
+      false => (self.pack_rows(levels, true), 0x01),
+    };
+    let raw: Vec<u8> = rows
+      .into_iter()
+      .flat_map(|row| std::iter::once(0x00).chain(row))
+      .collect();
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (index, block) in blocks.iter().enumerate() {
+      zlib.push((index == blocks.len() - 1) as u8);
+      zlib.extend((block.len() as u16).to_le_bytes());
+      zlib.extend((!(block.len() as u16)).to_le_bytes());
+      zlib.extend(*block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut ihdr = vec![];
+    ihdr.extend((self.size() as u32).to_be_bytes());
+    ihdr.extend((self.size() as u32).to_be_bytes());
+    ihdr.extend([depth, 0x00, 0x00, 0x00, 0x00]);
+
+    let mut bytes = b"\x89PNG\r\n\x1A\n".to_vec();
+    bytes.extend(chunk(b"IHDR", &ihdr));
+    bytes.extend(chunk(b"IDAT", &zlib));
+    bytes.extend(chunk(b"IEND", &[]));
+    bytes
+  }
+
+  fn encode_gif(&self, clocks: u128) -> Vec<u8> {
+    // https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+    // two-color global palette with lit pixels white, or a sixteen-color grayscale palette if any
+    // pixel is partially lit, looping forever
+    let centiseconds = |clocks: u128| clocks * 100 / self.clock_speed;
+    let gray = self.frames.iter().any(|(_, levels)| is_gray(levels));
+    let (bits, min_code_size) = match gray {
+      true => (0x04, 0x04),
+      false => (0x01, 0x02),
+    };
+
+    let size = (self.size() as u16).to_le_bytes();
+    let mut bytes = b"GIF89a".to_vec();
+    bytes.extend(size);
+    bytes.extend(size);
+    bytes.extend([0x80 | (bits - 1), 0x00, 0x00]);
+    for color in 0..1u8 << bits {
+      let shade = (color as usize * 0xFF / ((1 << bits) - 1)) as u8;
+      bytes.extend([shade, shade, shade]);
+    }
+    bytes.extend([0x21, 0xFF, 0x0B]);
+    bytes.extend(b"NETSCAPE2.0");
+    bytes.extend([0x03, 0x01, 0x00, 0x00, 0x00]);
+
+    let ends = self.frames.iter().skip(1).map(|(start, _)| *start);
+    for ((start, levels), end) in self.frames.iter().zip(ends.chain([clocks])) {
+      // frames superseded within the same centisecond are never seen
+      let delay = centiseconds(end) - centiseconds(*start);
+      if delay == 0 && end != clocks {
+        continue;
+      }
+
+      bytes.extend([0x21, 0xF9, 0x04, 0x00]);
+      bytes.extend((delay.min(0xFFFF) as u16).to_le_bytes());
+      bytes.extend([0x00, 0x00]);
+      bytes.extend([0x2C, 0x00, 0x00, 0x00, 0x00]);
+      bytes.extend(size);
+      bytes.extend(size);
+      bytes.push(0x00);
+
+      let indices: Vec<u8> = self
+        .pixels(levels)
+        .concat()
+        .into_iter()
+        .map(|level| level >> (0x08 - bits))
+        .collect();
+      bytes.push(min_code_size);
+      for block in gif_lzw(&indices, min_code_size).chunks(0xFF) {
+        bytes.push(block.len() as u8);
+        bytes.extend(block);
+      }
+      bytes.push(0x00);
+    }
+
+    bytes.push(0x3B);
+    bytes
+  }
+}
+
+fn gif_lzw(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+  // variable-width codes packed least significant bit first, table reset when full
+  let clear = 1u16 << min_code_size;
+  let eoi = clear + 1;
+
+  let mut bytes = vec![];
+  let (mut acc, mut bits) = (0u32, 0);
+  let mut width = min_code_size + 1;
+  let mut emit = |code: u16, width: u8| {
+    acc |= (code as u32) << bits;
+    bits += width;
+    while bits >= 8 {
+      bytes.push(acc as u8);
+      acc >>= 8;
+      bits -= 8;
+    }
+  };
+
+  let mut table: BTreeMap<(u16, u8), u16> = BTreeMap::new();
+  let mut next = eoi + 1;
+  let mut prefix: Option<u16> = None;
+
+  emit(clear, width);
+  for &index in indices {
+    prefix = Some(match prefix {
+      None => index as u16,
+      Some(prefix) => match table.get(&(prefix, index)) {
+        Some(&code) => code,
+        None => {
+          emit(prefix, width);
+          if next == 0x1000 {
+            emit(clear, width);
+            table.clear();
+            next = eoi + 1;
+            width = min_code_size + 1;
+          } else {
+            table.insert((prefix, index), next);
+            next += 1;
+            // the decoder widens its codes as soon as the next code no longer fits
+            if next > 1 << width && width < 12 {
+              width += 1;
+            }
+          }
+          index as u16
+        }
+      },
+    });
+  }
+  if let Some(prefix) = prefix {
+    emit(prefix, width);
+    // the decoder counts this last code as a table entry too
+    if next + 1 > 1 << width && width < 12 {
+      width += 1;
+    }
+  }
+  emit(eoi, width);
+  if bits > 0 {
+    bytes.push(acc as u8);
+  }
+
+  bytes
+}
@@ -1,71 +1,1389 @@
 #![allow(dead_code)]
 
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::Write;
 use std::path::PathBuf;
 
+mod capture;
+mod debugger;
+mod gdb;
+mod keymap;
+mod serial;
+mod snapshot;
+mod sound;
+
+use capture::parse_capture;
+pub use capture::{Capture, CaptureFormat};
+use debugger::Debugger;
+pub use gdb::execute_gdb;
+pub use keymap::{load_key_map, parse_key_map, KeyAction, KeyMap};
+pub use serial::{create_link, Serial};
+pub use snapshot::{load_snapshot, Snapshot};
+pub use sound::{create_sound, Sound};
+
 pub const MEM_SIZE: usize = 0x100;
 pub const MIC_SIZE: usize = 0x2000; // 0x80 * 0x02 * 0x20
 pub const DISPLAY_BUFFER: usize = 0xE0;
 pub const DISPLAY_BUFFER_LEN: usize = 0x20;
 pub const STDIO_BUFFER: usize = 0x00;
+pub const TIMER_TICKS: usize = 0xDB;
+pub const TIMER_CLOCKS: usize = 0xDC; // little-endian, two bytes
+pub const SOUND_TONE: usize = 0xDE;
+pub const SOUND_CONTROL: usize = 0xDF;
+pub const DEFAULT_CLOCK_SPEED: u128 = 1000000;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ControlWord {
+  pub data_ip: Signal, // data bus to instruction pointer
+  pub data_sp: Signal, // data bus to stack pointer
+  pub data_cf: Signal, // data bus to carry flag
+  pub data_il: Signal, // data bus to instruction latch
+  pub data_al: Signal, // data bus to address latch
+  pub data_xl: Signal, // data bus to X latch
+  pub data_yl: Signal, // data bus to Y latch
+  pub data_zl: Signal, // data bus to Z latch
+
+  pub mem_data: Signal,  // data bus to memory
+  pub data_mem: Signal,  // memory to data bus
+  pub clr_sc: Signal,    // clear to step counter
+  pub set_cin: Signal,   // set to carry in
+  pub ip_data: Signal,   // instruction pointer to data bus
+  pub sp_data: Signal,   // stack pointer to data bus
+  pub sum_data: Signal,  // sum to data bus
+  pub nand_data: Signal, // not-and to data bus
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Signal {
+  #[default]
+  Inactive,
+  Active,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TickTrap {
+  // microinstruction level
+  MicrocodeFault,
+  BusContention,
+  // instruction level
+  IllegalOpcode,
+  DebugRequest,
+  StackOverflow,
+  CodeCollision,
+  UninitializedRead,
+  CodeModification,
+  DataExecution,
+  Halted,
+}
+
+pub trait Tickable {
+  fn reset(
+    &mut self,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; DISPLAY_BUFFER_LEN],
+    controller: &mut u8,
+  );
+  fn tick(
+    &mut self,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; DISPLAY_BUFFER_LEN],
+    controller: &mut u8,
+  ) -> Result<u128, TickTrap>;
+  // whether the last call to `tick` completed an instruction
+  fn boundary(&self) -> bool;
+  // memory accesses performed by the last call to `tick`
+  fn accesses(&self) -> &[Access];
+  fn ip(&self) -> u8;
+  fn sp(&self) -> u8;
+  fn cf(&self) -> bool;
+  fn mem(&self) -> &[u8; MEM_SIZE];
+  fn set_ip(&mut self, ip: u8);
+  fn set_sp(&mut self, sp: u8);
+  fn set_cf(&mut self, cf: bool);
+  fn mem_mut(&mut self) -> &mut [u8; MEM_SIZE];
+  // record describing the last call to `tick` for execution traces, if any
+  fn trace(&self) -> Option<String>;
+  // serialize and deserialize machine state for snapshots
+  fn save(&self) -> Vec<u8>;
+  fn restore(&mut self, state: &[u8]);
+  // memory-mapped peripherals the machine accesses memory through
+  fn bus(&self) -> &Bus;
+  fn bus_mut(&mut self) -> &mut Bus;
+}
+
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+  // eight flags per byte, least significant bit first
+  (bits.chunks(8))
+    .map(|chunk| (chunk.iter().rev()).fold(0x00, |byte, bit| byte << 1 | *bit as u8))
+    .collect()
+}
+
+pub fn unpack_bits(bytes: &[u8]) -> Vec<bool> {
+  (bytes.iter())
+    .flat_map(|byte| (0..8).map(move |bit| byte >> bit & 0x01 != 0x00))
+    .collect()
+}
+
+pub fn save_dirty(dirty: &[(u8, u8)]) -> Vec<u8> {
+  // bytes written since the last `sti` along with their value back then, as a fixed-size mask
+  // and values so machine state keeps the same size throughout
+  let mut values = [None; MEM_SIZE];
+  for (address, value) in dirty {
+    values[*address as usize] = Some(*value);
+  }
+  let mut state = pack_bits(&values.map(|value| value.is_some()));
+  state.extend(values.map(|value| value.unwrap_or_default()));
+  state
+}
+
+pub fn restore_dirty(state: &[u8]) -> Vec<(u8, u8)> {
+  let (mask, values) = state.split_at(MEM_SIZE / 8);
+  (unpack_bits(mask).into_iter().zip(values).enumerate())
+    .filter(|(_, (dirty, _))| *dirty)
+    .map(|(address, (_, value))| (address as u8, *value))
+    .collect()
+}
+
+pub const DIRTY_STATE_LEN: usize = MEM_SIZE / 8 + MEM_SIZE;
+
+pub struct Io<'a> {
+  pub stdin: &'a mut VecDeque<u8>,
+  pub stdout: &'a mut VecDeque<u8>,
+  pub display: &'a mut [u8; DISPLAY_BUFFER_LEN],
+  pub controller: &'a mut u8,
+}
+
+pub trait Peripheral {
+  // addresses the peripheral is mapped at
+  fn range(&self) -> std::ops::RangeInclusive<u8>;
+  // value driven onto the data bus on a read, if any. `peek` must not have side effects
+  fn peek(&self, address: u8, io: &Io) -> Option<u8>;
+  fn read(&mut self, address: u8, io: &mut Io) -> Option<u8> {
+    self.peek(address, io)
+  }
+  // whether a write is absorbed by the peripheral instead of reaching memory
+  fn write(&mut self, address: u8, value: u8, io: &mut Io) -> bool;
+  // whether accesses do more than store bytes, so that a program repeating them is not stuck.
+  // plain storage such as memory banks does not, and only counts when its contents change
+  fn has_side_effects(&self) -> bool {
+    true
+  }
+  fn tick(&mut self, _clocks: u128, _io: &mut Io) {}
+  fn reset(&mut self, _mem: &[u8; MEM_SIZE], _io: &mut Io) {}
+  // serialize and deserialize peripheral state for snapshots
+  fn save(&self) -> Vec<u8> {
+    vec![]
+  }
+  fn restore(&mut self, _state: &[u8]) {}
+  // write out any buffered output before the machine is shut down
+  fn flush(&mut self) -> Result<(), Error> {
+    Ok(())
+  }
+}
+
+pub struct Bus {
+  peripherals: Vec<Box<dyn Peripheral>>,
+}
+
+impl Bus {
+  pub fn attach(&mut self, peripheral: Box<dyn Peripheral>) {
+    // peripherals are consulted in the order they were attached
+    self.peripherals.push(peripheral);
+  }
+
+  pub fn attach_front(&mut self, peripheral: Box<dyn Peripheral>) {
+    // takes precedence over every peripheral attached so far
+    self.peripherals.insert(0, peripheral);
+  }
+
+  pub fn peek(&self, address: u8, io: &Io) -> Option<u8> {
+    (self.peripherals.iter())
+      .filter(|peripheral| peripheral.range().contains(&address))
+      .find_map(|peripheral| peripheral.peek(address, io))
+  }
+
+  pub fn read(&mut self, address: u8, io: &mut Io) -> Option<u8> {
+    (self.peripherals.iter_mut())
+      .filter(|peripheral| peripheral.range().contains(&address))
+      .find_map(|peripheral| peripheral.read(address, io))
+  }
+
+  pub fn write(&mut self, address: u8, value: u8, io: &mut Io) -> bool {
+    (self.peripherals.iter_mut())
+      .filter(|peripheral| peripheral.range().contains(&address))
+      .any(|peripheral| peripheral.write(address, value, io))
+  }
+
+  pub fn has_side_effects(&self, address: u8) -> bool {
+    (self.peripherals.iter())
+      .filter(|peripheral| peripheral.range().contains(&address))
+      .any(|peripheral| peripheral.has_side_effects())
+  }
+
+  pub fn tick(&mut self, clocks: u128, io: &mut Io) {
+    for peripheral in &mut self.peripherals {
+      peripheral.tick(clocks, io);
+    }
+  }
+
+  pub fn reset(&mut self, mem: &[u8; MEM_SIZE], io: &mut Io) {
+    for peripheral in &mut self.peripherals {
+      peripheral.reset(mem, io);
+    }
+  }
+
+  pub fn save(&self) -> Vec<u8> {
+    // the state of every peripheral, prefixed by its length
+    let mut state = vec![];
+    for peripheral in &self.peripherals {
+      let peripheral_state = peripheral.save();
+      state.extend((peripheral_state.len() as u32).to_le_bytes());
+      state.extend(peripheral_state);
+    }
+    state
+  }
+
+  pub fn restore(&mut self, mut state: &[u8]) {
+    for peripheral in &mut self.peripherals {
+      let (len, rest) = state.split_at(4);
+      let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+      let (peripheral_state, rest) = rest.split_at(len);
+      peripheral.restore(peripheral_state);
+      state = rest;
+    }
+  }
+
+  pub fn flush(&mut self) -> Result<(), Error> {
+    // every peripheral is flushed even if one fails, the first failure is returned
+    (self.peripherals.iter_mut())
+      .map(|peripheral| peripheral.flush())
+      .fold(Ok(()), Result::and)
+  }
+}
+
+impl Default for Bus {
+  fn default() -> Bus {
+    Bus {
+      peripherals: vec![Box::new(Stdio), Box::new(Controller), Box::new(Display)],
+    }
+  }
+}
+
+pub struct Stdio;
+
+impl Peripheral for Stdio {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    STDIO_BUFFER as u8..=STDIO_BUFFER as u8
+  }
+
+  fn peek(&self, _address: u8, io: &Io) -> Option<u8> {
+    io.stdin.front().copied()
+  }
+
+  fn read(&mut self, _address: u8, io: &mut Io) -> Option<u8> {
+    io.stdin.pop_front()
+  }
+
+  fn write(&mut self, _address: u8, value: u8, io: &mut Io) -> bool {
+    io.stdout.push_back(value);
+    true
+  }
+
+  fn reset(&mut self, mem: &[u8; MEM_SIZE], io: &mut Io) {
+    // the byte at `STDIO_BUFFER` in the memory image is the first byte of `stdin`
+    io.stdin.clear();
+    io.stdout.clear();
+    io.stdin.push_back(mem[STDIO_BUFFER]);
+  }
+}
+
+pub struct Controller;
+
+impl Peripheral for Controller {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    // shares its address with `Stdio` and is only read from when `stdin` is empty
+    STDIO_BUFFER as u8..=STDIO_BUFFER as u8
+  }
+
+  fn peek(&self, _address: u8, io: &Io) -> Option<u8> {
+    Some(*io.controller)
+  }
+
+  fn write(&mut self, _address: u8, _value: u8, _io: &mut Io) -> bool {
+    false
+  }
+}
+
+pub struct Display;
+
+impl Peripheral for Display {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    DISPLAY_BUFFER as u8..=(DISPLAY_BUFFER + DISPLAY_BUFFER_LEN - 1) as u8
+  }
+
+  fn peek(&self, _address: u8, _io: &Io) -> Option<u8> {
+    None // reads are served by memory
+  }
+
+  fn write(&mut self, address: u8, value: u8, io: &mut Io) -> bool {
+    // mirrors memory rather than replacing it
+    io.display[address as usize - DISPLAY_BUFFER] = value;
+    false
+  }
+
+  fn reset(&mut self, mem: &[u8; MEM_SIZE], io: &mut Io) {
+    io.display
+      .copy_from_slice(&mem[DISPLAY_BUFFER..DISPLAY_BUFFER + DISPLAY_BUFFER_LEN]);
+  }
+}
+
+#[derive(Clone)]
+pub struct Banks {
+  pages: Vec<Vec<u8>>, // external pages of memory, all of the same size
+  select: u8,          // bank-select register
+}
+
+impl Banks {
+  pub fn new(pages: Vec<Vec<u8>>) -> Banks {
+    Banks {
+      pages,
+      select: 0x00,
+    }
+  }
+
+  pub fn window(&self) -> usize {
+    // pages are mapped right below the display buffer
+    DISPLAY_BUFFER - self.pages[0].len()
+  }
+
+  pub fn register(&self) -> usize {
+    // the bank-select register sits right below the window
+    self.window() - 1
+  }
+
+  pub fn pages(&self) -> &[Vec<u8>] {
+    &self.pages
+  }
+
+  fn page(&self) -> usize {
+    self.select as usize % self.pages.len()
+  }
+}
+
+impl Peripheral for Banks {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    self.register() as u8..=(DISPLAY_BUFFER - 1) as u8
+  }
+
+  fn peek(&self, address: u8, _io: &Io) -> Option<u8> {
+    match address as usize {
+      address if address == self.register() => Some(self.select),
+      address => Some(self.pages[self.page()][address - self.window()]),
+    }
+  }
+
+  fn write(&mut self, address: u8, value: u8, _io: &mut Io) -> bool {
+    match address as usize {
+      address if address == self.register() => self.select = value,
+      address => {
+        let (page, window) = (self.page(), self.window());
+        self.pages[page][address - window] = value;
+      }
+    }
+    true
+  }
+
+  fn has_side_effects(&self) -> bool {
+    false
+  }
+
+  fn reset(&mut self, _mem: &[u8; MEM_SIZE], _io: &mut Io) {
+    self.select = 0x00;
+  }
+
+  fn save(&self) -> Vec<u8> {
+    let mut state = vec![self.select];
+    state.extend(self.pages.concat());
+    state
+  }
+
+  fn restore(&mut self, state: &[u8]) {
+    let (select, pages) = state.split_first().unwrap();
+    self.select = *select;
+    let page_len = self.pages[0].len();
+    for (page, page_state) in self.pages.iter_mut().zip(pages.chunks(page_len)) {
+      page.copy_from_slice(page_state);
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct Timer {
+  clock_speed: u128, // clocks per second for tick timing
+  clocks: u128,      // clocks elapsed since reset
+  ticks: u8,         // tick register, incremented `TICK_RATE` times per second
+  counter: u16,      // elapsed-clock counter
+  latch: u8,         // high byte of `counter` as of the last read of its low byte
+}
+
+const TICK_RATE: u128 = 60;
+
+impl Timer {
+  pub fn new(clock_speed: u128) -> Timer {
+    Timer {
+      clock_speed,
+      clocks: 0,
+      ticks: 0x00,
+      counter: 0x0000,
+      latch: 0x00,
+    }
+  }
+}
+
+impl Peripheral for Timer {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    TIMER_TICKS as u8..=(TIMER_CLOCKS + 1) as u8
+  }
+
+  fn peek(&self, address: u8, _io: &Io) -> Option<u8> {
+    match address as usize {
+      TIMER_TICKS => Some(self.ticks),
+      TIMER_CLOCKS => Some(self.counter.to_le_bytes()[0]),
+      _ => Some(self.latch),
+    }
+  }
+
+  fn read(&mut self, address: u8, io: &mut Io) -> Option<u8> {
+    // reading the low byte latches the high byte so both bytes are read at once
+    if address as usize == TIMER_CLOCKS {
+      self.latch = self.counter.to_le_bytes()[1];
+    }
+    self.peek(address, io)
+  }
+
+  fn write(&mut self, address: u8, value: u8, _io: &mut Io) -> bool {
+    match address as usize {
+      TIMER_TICKS => self.ticks = value,
+      _ => self.counter = 0x0000,
+    }
+    true
+  }
+
+  fn tick(&mut self, clocks: u128, _io: &mut Io) {
+    // tick `n` happens at clock `n * clock_speed / TICK_RATE`
+    let before = self.clocks * TICK_RATE / self.clock_speed;
+    self.clocks += clocks;
+    let after = self.clocks * TICK_RATE / self.clock_speed;
+    self.ticks = self.ticks.wrapping_add((after - before) as u8);
+    self.counter = self.counter.wrapping_add(clocks as u16);
+  }
+
+  fn reset(&mut self, _mem: &[u8; MEM_SIZE], _io: &mut Io) {
+    *self = Timer::new(self.clock_speed);
+  }
+
+  fn save(&self) -> Vec<u8> {
+    let mut state = self.clocks.to_le_bytes().to_vec();
+    state.push(self.ticks);
+    state.extend(self.counter.to_le_bytes());
+    state.push(self.latch);
+    state
+  }
+
+  fn restore(&mut self, state: &[u8]) {
+    let (clocks, state) = state.split_at(16);
+    self.clocks = u128::from_le_bytes(clocks.try_into().unwrap());
+    self.ticks = state[0];
+    self.counter = u16::from_le_bytes([state[1], state[2]]);
+    self.latch = state[3];
+  }
+}
+
+pub fn create_timer(flags: &BTreeMap<String, String>) -> Result<Option<Timer>, Error> {
+  flags
+    .contains_key("timer")
+    .then(|| parse_nominal_clock_speed(flags).map(Timer::new))
+    .transpose()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+  Read(u8),
+  Write(u8),
+}
+
+pub fn parse_flags(args: Vec<String>) -> (Vec<String>, BTreeMap<String, String>) {
+  // split `--flag` and `--flag=value` arguments from positional arguments
+
+  let (flags, args): (Vec<String>, Vec<String>) =
+    args.into_iter().partition(|arg| arg.starts_with("--"));
+
+  let flags = flags
+    .into_iter()
+    .map(|flag| match flag["--".len()..].split_once('=') {
+      Some((name, value)) => (name.to_string(), value.to_string()),
+      None => (flag["--".len()..].to_string(), "".to_string()),
+    })
+    .collect();
+
+  (args, flags)
+}
+
+pub fn parse_count(flags: &BTreeMap<String, String>, name: &str) -> Result<Option<u128>, Error> {
+  flags
+    .get(name)
+    .map(|value| {
+      value
+        .parse::<u128>()
+        .map_err(|_| Error(format!("Invalid value '{}' for flag '--{}'", value, name)))
+    })
+    .transpose()
+}
+
+pub struct Batch {
+  pub stdin: Vec<u8>,
+  pub stdout: Box<dyn Write>,
+  pub max_clocks: Option<u128>,
+  pub max_instructions: Option<u128>,
+  pub snapshot_file: Option<String>,
+  pub trace: Option<Box<dyn Write>>,
+  pub profile: Option<Box<dyn Write>>,
+  pub profile_tokens: bool,
+  pub debug_info: Option<DebugInfo>,
+  pub replay: Option<Replay>,
+  pub capture: Option<Capture>,
+  pub phosphor: Option<Phosphor>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Stop {
+  Halted,
+  Trapped(TickTrap),
+  TimedOut,
+  Diverged,
+}
+
+pub fn parse_stdin(flags: &BTreeMap<String, String>) -> Result<Vec<u8>, Error> {
+  Ok(match flags.get("stdin").map(String::as_str) {
+    None => vec![],
+    Some("-") => {
+      let mut stdin = vec![];
+      std::io::Read::read_to_end(&mut std::io::stdin(), &mut stdin)
+        .map_err(|_| Error("Unable to read standard input".to_string()))?;
+      stdin
+    }
+    Some(file) => {
+      std::fs::read(file).map_err(|_| Error(format!("Unable to read file '{}'", file)))?
+    }
+  })
+}
+
+pub fn parse_batch(flags: &BTreeMap<String, String>) -> Result<Batch, Error> {
+  let stdin = parse_stdin(flags)?;
+
+  fn create(file: &str) -> Result<Box<dyn Write>, Error> {
+    Ok(match file {
+      "-" => Box::new(std::io::stdout()),
+      file => Box::new(std::io::BufWriter::new(
+        std::fs::File::create(file)
+          .map_err(|_| Error(format!("Unable to write file '{}'", file)))?,
+      )),
+    })
+  }
+
+  let stdout = create(flags.get("stdout").map(String::as_str).unwrap_or("-"))?;
+  let trace = flags.get("trace").map(|file| create(file)).transpose()?;
+  let profile = flags.get("profile").map(|file| create(file)).transpose()?;
+
+  Ok(Batch {
+    stdin,
+    stdout,
+    max_clocks: parse_count(flags, "clocks")?,
+    max_instructions: parse_count(flags, "instructions")?,
+    snapshot_file: flags.get("snapshot").cloned(),
+    trace,
+    profile,
+    profile_tokens: flags.contains_key("profile-tokens"),
+    debug_info: load_debug_info(flags)?,
+    replay: load_replay(flags)?,
+    capture: parse_capture(flags)?,
+    phosphor: parse_phosphor(flags)?,
+  })
+}
+
+pub fn execute_batch<MC: Tickable>(
+  mut mc: MC,
+  mut batch: Batch,
+  snapshot: Option<Snapshot>,
+) -> Result<(Stop, u128), Error> {
+  let mut current_clocks = 0;
+  let mut current_instructions = 0;
+
+  let mut stdin = VecDeque::new();
+  let mut stdout = VecDeque::new();
+  let mut display = [0x00; DISPLAY_BUFFER_LEN];
+
+  mc.reset(&mut stdin, &mut stdout, &mut display, &mut 0x00);
+  if let Some(snapshot) = snapshot {
+    current_clocks = snapshot.apply(&mut mc, &mut stdin, &mut stdout, &mut display);
+  }
+  stdin.extend(batch.stdin);
+
+  // a closed pipe on `stdout` ends execution with an error rather than a panic
+  let stdout_error = || Error("Unable to write standard output".to_string());
+  let trace_error = || Error("Unable to write trace".to_string());
+
+  let mut profile = Profile::default();
+  let mut address = mc.ip();
+  if let Some(capture) = &mut batch.capture {
+    capture.frame(current_clocks, &display, batch.phosphor.as_mut());
+  }
+
+  let stop = loop {
+    let timed_out = batch.max_clocks.is_some_and(|max| current_clocks >= max)
+      || batch
+        .max_instructions
+        .is_some_and(|max| current_instructions >= max);
+    if timed_out {
+      break Stop::TimedOut;
+    }
+
+    // no controller is connected in batch mode, but replays may drive it
+    let mut controller = match &mut batch.replay {
+      Some(replay) => replay.feed(current_clocks, &mut stdin),
+      None => 0x00,
+    };
+    let start_clocks = current_clocks;
+    match mc.tick(&mut stdin, &mut stdout, &mut display, &mut controller) {
+      Ok(clocks) => current_clocks += clocks,
+      Err(TickTrap::Halted) => break Stop::Halted,
+      Err(tick_trap) => break Stop::Trapped(tick_trap),
+    }
+    if let (Some(trace), Some(record)) = (&mut batch.trace, mc.trace()) {
+      writeln!(trace, "{} {}", start_clocks, record).map_err(|_| trace_error())?;
+    }
+    profile.clocks[address as usize] += current_clocks - start_clocks;
+    // frames are captured before the phosphor sees the display as of the end of this tick
+    if let Some(capture) = &mut batch.capture {
+      capture.frame(current_clocks, &display, batch.phosphor.as_mut());
+    }
+    if let Some(phosphor) = &mut batch.phosphor {
+      phosphor.update(current_clocks, &display);
+    }
+    if mc.boundary() {
+      current_instructions += 1;
+      profile.counts[address as usize] += 1;
+      address = mc.ip();
+    }
+
+    if !stdout.is_empty() {
+      let bytes: Vec<u8> = stdout.drain(..).collect();
+      batch.stdout.write_all(&bytes).map_err(|_| stdout_error())?;
+    }
+  };
+
+  batch.stdout.flush().map_err(|_| stdout_error())?;
+  mc.bus_mut().flush()?;
+  if let Some(trace) = &mut batch.trace {
+    trace.flush().map_err(|_| trace_error())?;
+  }
+  if let Some(capture) = &batch.capture {
+    capture.save(current_clocks)?;
+  }
+  if let Some(output) = &mut batch.profile {
+    write!(
+      output,
+      "{}",
+      profile.render(batch.debug_info.as_ref(), batch.profile_tokens)
+    )
+    .and_then(|()| output.flush())
+    .map_err(|_| Error("Unable to write profile".to_string()))?;
+  }
+
+  if let Some(snapshot_file) = &batch.snapshot_file {
+    Snapshot::take(&mc, &stdin, &stdout, &display, 0x00, current_clocks).save(snapshot_file)?;
+  }
+
+  Ok((stop, current_clocks))
+}
+
+pub fn execute_lockstep<R: Tickable, S: Tickable>(
+  name: &str,
+  mut reference: R,
+  mut subject: S,
+  mut batch: Batch,
+) -> Result<(Stop, u128), Error> {
+  // `reference` executes one instruction per tick whereas `subject` may take many ticks per
+  // instruction; architectural state is compared at every instruction boundary of `subject`
+
+  struct Io {
+    stdin: VecDeque<u8>,
+    stdout: VecDeque<u8>,
+    display: [u8; DISPLAY_BUFFER_LEN],
+  }
+
+  let stdout_error = || Error("Unable to write standard output".to_string());
+
+  let mut current_clocks = 0;
+  let mut current_instructions = 0;
+
+  let [mut reference_io, mut subject_io] = std::array::from_fn(|_| Io {
+    stdin: VecDeque::new(),
+    stdout: VecDeque::new(),
+    display: [0x00; DISPLAY_BUFFER_LEN],
+  });
+
+  // no controller is connected in lockstep mode
+  macro_rules! tick {
+    ($mc:expr, $io:expr) => {
+      $mc.tick(&mut $io.stdin, &mut $io.stdout, &mut $io.display, &mut 0x00)
+    };
+  }
+
+  reference.reset(
+    &mut reference_io.stdin,
+    &mut reference_io.stdout,
+    &mut reference_io.display,
+    &mut 0x00,
+  );
+  subject.reset(
+    &mut subject_io.stdin,
+    &mut subject_io.stdout,
+    &mut subject_io.display,
+    &mut 0x00,
+  );
+  reference_io.stdin.extend(&batch.stdin);
+  subject_io.stdin.extend(batch.stdin);
+
+  let stop = loop {
+    let timed_out = batch.max_clocks.is_some_and(|max| current_clocks >= max)
+      || batch
+        .max_instructions
+        .is_some_and(|max| current_instructions >= max);
+    if timed_out {
+      break Stop::TimedOut;
+    }
+
+    let reference_result = tick!(reference, reference_io);
+    let mut subject_clocks = 0;
+    let subject_result = loop {
+      match tick!(subject, subject_io) {
+        Ok(clocks) => subject_clocks += clocks,
+        Err(tick_trap) => break Err(tick_trap),
+      }
+      if subject.boundary() {
+        break Ok(subject_clocks);
+      }
+    };
+    current_clocks += subject_clocks;
+    current_instructions += 1;
+
+    let mut divergences = vec![];
+    let stop = match (reference_result, subject_result) {
+      (Ok(reference_clocks), subject_result) => {
+        if reference_clocks != subject_clocks {
+          divergences.push(format!(
+            "Reference took {} clocks but subject took {}",
+            reference_clocks, subject_clocks
+          ));
+        }
+        match subject_result {
+          Ok(_) => None,
+          // `subject` traps on the `sti` that reaches a fixed point, `reference` on the tick after
+          Err(TickTrap::Halted) => match tick!(reference, reference_io) {
+            Err(TickTrap::Halted) => Some(Stop::Halted),
+            _ => {
+              divergences.push("Subject halted but reference did not".to_string());
+              None
+            }
+          },
+          Err(subject_trap) => {
+            divergences.push(format!(
+              "Subject trapped on {:?} but reference did not",
+              subject_trap
+            ));
+            None
+          }
+        }
+      }
+      (Err(reference_trap), Ok(_)) => {
+        divergences.push(format!(
+          "Reference trapped on {:?} but subject did not",
+          reference_trap
+        ));
+        None
+      }
+      (Err(reference_trap), Err(subject_trap)) if reference_trap == subject_trap => {
+        break Stop::Trapped(subject_trap);
+      }
+      (Err(reference_trap), Err(subject_trap)) => {
+        divergences.push(format!(
+          "Reference trapped on {:?} but subject trapped on {:?}",
+          reference_trap, subject_trap
+        ));
+        None
+      }
+    };
+
+    for (name, reference, subject) in [
+      ("IP", reference.ip(), subject.ip()),
+      ("SP", reference.sp(), subject.sp()),
+      ("CF", reference.cf() as u8, subject.cf() as u8),
+    ] {
+      if reference != subject {
+        divergences.push(format!(
+          "{} is {:02X} in reference but {:02X} in subject",
+          name, reference, subject
+        ));
+      }
+    }
+    for (address, (reference, subject)) in reference.mem().iter().zip(subject.mem()).enumerate() {
+      if reference != subject {
+        divergences.push(format!(
+          "Memory at {:02X} is {:02X} in reference but {:02X} in subject",
+          address, reference, subject
+        ));
+      }
+    }
+    // output matched up to the previous instruction, so comparing new output suffices
+    if reference_io.stdout != subject_io.stdout {
+      divergences.push(format!(
+        "Output {:02X?} in reference but {:02X?} in subject",
+        reference_io.stdout, subject_io.stdout
+      ));
+    }
+
+    let bytes: Vec<u8> = subject_io.stdout.drain(..).collect();
+    batch.stdout.write_all(&bytes).map_err(|_| stdout_error())?;
+    reference_io.stdout.clear();
+
+    if !divergences.is_empty() {
+      eprintln!(
+        "{}: Divergence on instruction {} after {} clocks",
+        name, current_instructions, current_clocks
+      );
+      if let Some(record) = reference.trace() {
+        eprintln!("{}: Reference executed {}", name, record);
+      }
+      for divergence in divergences {
+        eprintln!("{}: {}", name, divergence);
+      }
+      break Stop::Diverged;
+    }
+    if let Some(stop) = stop {
+      break stop;
+    }
+  };
+
+  batch.stdout.flush().map_err(|_| stdout_error())?;
+
+  Ok((stop, current_clocks))
+}
+
+pub struct Profile {
+  pub clocks: [u128; MEM_SIZE], // clocks spent on instructions at each address
+  pub counts: [u128; MEM_SIZE], // instructions completed at each address
+}
+
+impl Default for Profile {
+  fn default() -> Profile {
+    Profile {
+      clocks: [0; MEM_SIZE],
+      counts: [0; MEM_SIZE],
+    }
+  }
+}
+
+impl Profile {
+  pub fn render(&self, debug_info: Option<&DebugInfo>, tokens: bool) -> String {
+    // with debug info, also per label and per macro, and per token of every macro if `tokens`
+    let total: u128 = self.clocks.iter().sum();
+
+    let mut addresses: BTreeMap<String, (u128, u128)> = BTreeMap::new();
+    let mut labels: BTreeMap<String, (u128, u128)> = BTreeMap::new();
+    let mut macros: BTreeMap<String, (u128, u128)> = BTreeMap::new();
+    let mut origins: BTreeMap<String, (u128, u128)> = BTreeMap::new();
+
+    for address in 0..MEM_SIZE {
+      let (clocks, count) = (self.clocks[address], self.counts[address]);
+      if clocks == 0 && count == 0 {
+        continue;
+      }
+
+      let accumulate = |entries: &mut BTreeMap<String, (u128, u128)>, key: String| {
+        let entry = entries.entry(key).or_default();
+        entry.0 += clocks;
+        entry.1 += count;
+      };
+
+      accumulate(&mut addresses, format!("{:02X}", address));
+      if let Some(debug_info) = debug_info {
+        let label = match debug_info.label_at(address as u8) {
+          Some((_, label)) => format!("{}", label),
+          None => "?".to_string(),
+        };
+        let (r#macro, origin) = match debug_info.origin_at(address as u8) {
+          Some((r#macro, pos)) => (format!("{}", r#macro), format!("{} {}", r#macro, pos)),
+          None => ("?".to_string(), "?".to_string()),
+        };
+        accumulate(&mut labels, label);
+        accumulate(&mut macros, r#macro);
+        accumulate(&mut origins, origin);
+      }
+    }
+
+    let render_entries = |title: &str, entries: BTreeMap<String, (u128, u128)>| {
+      // hottest entries first, ties broken by key
+      let mut entries: Vec<(String, (u128, u128))> = entries.into_iter().collect();
+      entries.sort_by(|(_, (a, _)), (_, (b, _))| b.cmp(a));
+      std::iter::once(format!(
+        "{:>12} {:>6} {:>12}  {}\n",
+        "CLOCKS", "%", "COUNT", title
+      ))
+      .chain(entries.into_iter().map(|(key, (clocks, count))| {
+        format!(
+          "{:>12} {:>6.2} {:>12}  {}\n",
+          clocks,
+          clocks as f64 * 100.0 / total.max(1) as f64,
+          count,
+          key
+        )
+      }))
+      .collect::<String>()
+    };
+
+    match (debug_info, tokens) {
+      (Some(_), false) => [
+        render_entries("ADDRESS", addresses),
+        render_entries("LABEL", labels),
+        render_entries("MACRO", macros),
+      ]
+      .join("\n"),
+      (Some(_), true) => [
+        render_entries("ADDRESS", addresses),
+        render_entries("LABEL", labels),
+        render_entries("MACRO", macros),
+        render_entries("ORIGIN", origins),
+      ]
+      .join("\n"),
+      (None, _) => render_entries("ADDRESS", addresses),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Input {
+  Stdin(u8),
+  Controller(u8),
+}
+
+pub struct Replay {
+  pub inputs: VecDeque<(u128, Input)>,
+  pub controller: u8,
+}
+
+impl Replay {
+  pub fn feed(&mut self, clocks: u128, stdin: &mut VecDeque<u8>) -> u8 {
+    // deliver every input the machine saw at or before `clocks`
+    while let Some((_, input)) = self.inputs.front().filter(|(at, _)| *at <= clocks) {
+      match *input {
+        Input::Stdin(c) => stdin.push_back(c),
+        Input::Controller(controller) => self.controller = controller,
+      }
+      self.inputs.pop_front();
+    }
+    self.controller
+  }
+}
+
+pub struct Recorder {
+  pub file: Box<dyn Write>,
+  pub controller: u8,
+}
+
+impl Recorder {
+  pub fn record(&mut self, clocks: u128, input: Input) -> Result<(), Error> {
+    // controller state is only recorded when it changes
+    if let Input::Controller(controller) = input {
+      if controller == self.controller {
+        return Ok(());
+      }
+      self.controller = controller;
+    }
+    writeln!(self.file, "{} {}", clocks, input)
+      .map_err(|_| Error("Unable to write input log, recording stopped".to_string()))
+  }
+}
+
+pub fn parse_replay(source: &str) -> Result<Replay, Error> {
+  // parse the input log written by `--record`, one `<clocks> stdin|controller XX` per line
+  let inputs = source
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(|line| {
+      let invalid = || Error(format!("Invalid replay record '{}'", line));
+      match line.split_whitespace().collect::<Vec<&str>>()[..] {
+        [clocks, kind, value] => {
+          let clocks = clocks.parse::<u128>().map_err(|_| invalid())?;
+          let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+          match kind {
+            "stdin" => Ok((clocks, Input::Stdin(value))),
+            "controller" => Ok((clocks, Input::Controller(value))),
+            _ => Err(invalid()),
+          }
+        }
+        _ => Err(invalid()),
+      }
+    })
+    .collect::<Result<VecDeque<(u128, Input)>, Error>>()?;
+
+  Ok(Replay {
+    inputs,
+    controller: 0x00,
+  })
+}
+
+pub fn load_replay(flags: &BTreeMap<String, String>) -> Result<Option<Replay>, Error> {
+  flags
+    .get("replay")
+    .map(|file| {
+      std::fs::read_to_string(file)
+        .map_err(|_| Error(format!("Unable to read file '{}'", file)))
+        .and_then(|source| parse_replay(&source))
+    })
+    .transpose()
+}
+
+pub fn create_recorder(flags: &BTreeMap<String, String>) -> Result<Option<Recorder>, Error> {
+  flags
+    .get("record")
+    .map(|file| {
+      // unbuffered as interactive sessions are usually ended by killing the process
+      Ok(Recorder {
+        file: Box::new(
+          std::fs::File::create(file)
+            .map_err(|_| Error(format!("Unable to write file '{}'", file)))?,
+        ),
+        controller: 0x00,
+      })
+    })
+    .transpose()
+}
+
+pub fn report_batch(name: &str, stop: Stop, clocks: u128) -> i32 {
+  // report on `stderr` as `stdout` may be carrying the program's output
+
+  match stop {
+    Stop::Halted => {
+      eprintln!("{}: Halted after {} clocks", name, clocks);
+      0
+    }
+    Stop::Trapped(tick_trap) => {
+      eprintln!(
+        "{}: Trapped on {:?} after {} clocks",
+        name, tick_trap, clocks
+      );
+      2
+    }
+    Stop::TimedOut => {
+      eprintln!("{}: Timed out after {} clocks", name, clocks);
+      3
+    }
+    Stop::Diverged => {
+      eprintln!("{}: Diverged after {} clocks", name, clocks);
+      4
+    }
+  }
+}
 
-#[derive(Clone, Copy, Debug, Default)]
-pub struct ControlWord {
-  pub data_ip: Signal, // data bus to instruction pointer
-  pub data_sp: Signal, // data bus to stack pointer
-  pub data_cf: Signal, // data bus to carry flag
-  pub data_il: Signal, // data bus to instruction latch
-  pub data_al: Signal, // data bus to address latch
-  pub data_xl: Signal, // data bus to X latch
-  pub data_yl: Signal, // data bus to Y latch
-  pub data_zl: Signal, // data bus to Z latch
+#[derive(Clone, Default)]
+pub struct DebugInfo {
+  pub labels: Vec<(u8, Label)>,
+  pub origins: Vec<(u8, Macro, Pos)>,
+  pub regions: Vec<(u8, u8, Region)>, // first and last address of every region
+}
 
-  pub mem_data: Signal,  // data bus to memory
-  pub data_mem: Signal,  // memory to data bus
-  pub clr_sc: Signal,    // clear to step counter
-  pub set_cin: Signal,   // set to carry in
-  pub ip_data: Signal,   // instruction pointer to data bus
-  pub sp_data: Signal,   // stack pointer to data bus
-  pub sum_data: Signal,  // sum to data bus
-  pub nand_data: Signal, // not-and to data bus
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Region {
+  Code,
+  Data,
+  Display,
+  Stack,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-pub enum Signal {
-  #[default]
-  Inactive,
-  Active,
+impl DebugInfo {
+  pub fn label_at(&self, address: u8) -> Option<(u8, &Label)> {
+    // nearest label at or below `address`
+    self
+      .labels
+      .iter()
+      .filter(|(label_address, _)| *label_address <= address)
+      .max_by_key(|(label_address, _)| *label_address)
+      .map(|(label_address, label)| (address - label_address, label))
+  }
+
+  pub fn origin_at(&self, address: u8) -> Option<(&Macro, &Pos)> {
+    self
+      .origins
+      .iter()
+      .find(|(origin_address, _, _)| *origin_address == address)
+      .map(|(_, r#macro, pos)| (r#macro, pos))
+  }
+
+  pub fn region_at(&self, address: u8) -> Option<Region> {
+    self
+      .regions
+      .iter()
+      .find(|(first, last, _)| (*first..=*last).contains(&address))
+      .map(|(_, _, region)| *region)
+  }
+
+  pub fn render(&self, address: u8) -> String {
+    let label = match self.label_at(address) {
+      Some((0x00, label)) => format!("{}", label),
+      Some((offset, label)) => format!("{} + {:02X}", label, offset),
+      None => "".to_string(),
+    };
+
+    let (origin, line) = match self.origin_at(address) {
+      Some((r#macro, pos)) => (
+        format!("{} {}", r#macro, pos),
+        std::fs::read_to_string(&(pos.0).0)
+          .ok()
+          .and_then(|source| {
+            source
+              .lines()
+              .nth(pos.1)
+              .map(|line| line.trim().to_string())
+          })
+          .unwrap_or("".to_string()),
+      ),
+      None => ("".to_string(), "".to_string()),
+    };
+
+    format!("LBL  {}\r\nSRC  {}\r\n     {}\r\n", label, origin, line)
+  }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum TickTrap {
-  // microinstruction level
-  MicrocodeFault,
-  BusContention,
-  // instruction level
-  IllegalOpcode,
-  DebugRequest,
+pub fn parse_debug_info(source: &str) -> Result<DebugInfo, Error> {
+  // parse the debug info file written by `asm --debug`
+
+  let mut debug_info = DebugInfo::default();
+
+  for (row, line) in source.lines().enumerate() {
+    let line = line.split('#').next().unwrap().trim();
+    let error = || Error(format!("Malformed debug info on line {}", row + 1));
+    let mut fields = line.splitn(4, ' ');
+
+    match fields.next() {
+      Some("") => (),
+      Some("label") => {
+        let address = fields
+          .next()
+          .and_then(|address| u8::from_str_radix(address, 16).ok());
+        let token = fields
+          .next()
+          .map(|label| mnemonic_to_token(Mnemonic(label.to_string())));
+        match (address, token) {
+          (Some(address), Some(Some(Token::LabelDef(label)))) => {
+            debug_info.labels.push((address, label))
+          }
+          _ => Err(error())?,
+        }
+      }
+      Some("origin") => {
+        let address = fields
+          .next()
+          .and_then(|address| u8::from_str_radix(address, 16).ok());
+        let token = fields
+          .next()
+          .map(|r#macro| mnemonic_to_token(Mnemonic(r#macro.to_string())));
+        let pos = fields.next().and_then(|pos| {
+          let mut parts = pos.strip_prefix('@')?.rsplitn(3, ':');
+          let col = parts.next()?.parse::<usize>().ok()?.checked_sub(1)?;
+          let row = parts.next()?.parse::<usize>().ok()?.checked_sub(1)?;
+          Some(Pos(File(parts.next()?.into()), row, col))
+        });
+        match (address, token, pos) {
+          (Some(address), Some(Some(Token::MacroRef(r#macro))), Some(pos)) => {
+            debug_info.origins.push((address, r#macro, pos))
+          }
+          _ => Err(error())?,
+        }
+      }
+      Some("region") => {
+        let first = fields
+          .next()
+          .and_then(|first| u8::from_str_radix(first, 16).ok());
+        let last = fields
+          .next()
+          .and_then(|last| u8::from_str_radix(last, 16).ok());
+        let region = match fields.next() {
+          Some("code") => Some(Region::Code),
+          Some("data") => Some(Region::Data),
+          Some("display") => Some(Region::Display),
+          Some("stack") => Some(Region::Stack),
+          _ => None,
+        };
+        match (first, last, region) {
+          (Some(first), Some(last), Some(region)) if first <= last => {
+            debug_info.regions.push((first, last, region))
+          }
+          _ => Err(error())?,
+        }
+      }
+      Some(_) | None => Err(error())?,
+    }
+  }
+
+  Ok(debug_info)
 }
 
-pub trait Tickable {
-  fn reset(
-    &mut self,
-    stdin: &mut VecDeque<u8>,
-    stdout: &mut VecDeque<u8>,
-    display: &mut [u8; DISPLAY_BUFFER_LEN],
-    controller: &mut u8,
-  );
-  fn tick(
-    &mut self,
-    stdin: &mut VecDeque<u8>,
-    stdout: &mut VecDeque<u8>,
-    display: &mut [u8; DISPLAY_BUFFER_LEN],
-    controller: &mut u8,
-  ) -> Result<u128, TickTrap>;
+pub fn load_debug_info(flags: &BTreeMap<String, String>) -> Result<Option<DebugInfo>, Error> {
+  flags
+    .get("debug")
+    .map(|file| {
+      std::fs::read_to_string(file)
+        .map_err(|_| Error(format!("Unable to read file '{}'", file)))
+        .and_then(|source| parse_debug_info(&source))
+    })
+    .transpose()
+}
+
+pub fn parse_bank_size(flags: &BTreeMap<String, String>) -> Result<usize, Error> {
+  match flags.get("bank-size").map(String::as_str) {
+    None | Some("64") => Ok(0x40),
+    Some("128") => Ok(0x80),
+    Some(value) => Err(Error(format!(
+      "Invalid value '{}' for flag '--bank-size'",
+      value
+    ))),
+  }
+}
+
+pub fn load_memory_image(
+  file: &str,
+  flags: &BTreeMap<String, String>,
+) -> Result<([u8; MEM_SIZE], Option<Banks>), Error> {
+  // main memory, optionally followed by the pages of a memory expansion
+
+  let bytes = std::fs::read(file).map_err(|_| Error(format!("Unable to read file '{}'", file)))?;
+  let bank_size = parse_bank_size(flags)?;
+  let incorrect_size = || Error(format!("Memory image '{}' has incorrect size", file));
+
+  let (memory_image, pages) = bytes
+    .split_at_checked(MEM_SIZE)
+    .ok_or_else(incorrect_size)?;
+  if pages.len() % bank_size != 0 {
+    Err(incorrect_size())?;
+  }
+  let banks =
+    (!pages.is_empty()).then(|| Banks::new(pages.chunks(bank_size).map(Vec::from).collect()));
+
+  Ok((memory_image.try_into().unwrap(), banks))
+}
+
+pub fn parse_clock_speed(flags: &BTreeMap<String, String>) -> Result<Option<u128>, Error> {
+  // `None` runs as fast as possible
+  match flags.get("clock-speed").map(String::as_str) {
+    None => Ok(Some(DEFAULT_CLOCK_SPEED)),
+    Some("max") => Ok(None),
+    Some(value) => match value.parse::<u128>() {
+      Ok(clock_speed) if clock_speed > 0 => Ok(Some(clock_speed)),
+      _ => Err(Error(format!(
+        "Invalid value '{}' for flag '--clock-speed'",
+        value
+      ))),
+    },
+  }
+}
+
+pub fn parse_nominal_clock_speed(flags: &BTreeMap<String, String>) -> Result<u128, Error> {
+  // emulated clocks are timed at the nominal clock speed, even when running as fast as possible
+  Ok(parse_clock_speed(flags)?.unwrap_or(DEFAULT_CLOCK_SPEED))
+}
+
+pub fn parse_phosphor(flags: &BTreeMap<String, String>) -> Result<Option<Phosphor>, Error> {
+  // decays over one frame at the nominal clock speed by default
+  let default = parse_nominal_clock_speed(flags)? / 60;
+  match flags.get("phosphor").map(String::as_str) {
+    None => Ok(None),
+    Some("") => Ok(Some(Phosphor::new(default.max(1)))),
+    Some(value) => match value.parse::<u128>() {
+      Ok(decay) if decay > 0 => Ok(Some(Phosphor::new(decay))),
+      _ => Err(Error(format!(
+        "Invalid value '{}' for flag '--phosphor'",
+        value
+      ))),
+    },
+  }
 }
 
-pub fn execute<MC: std::fmt::Display + Tickable>(mut mc: MC, clock_speed: u128) {
+pub struct InteractiveOptions {
+  pub clock_speed: Option<u128>,
+  pub debug_info: Option<DebugInfo>,
+  pub snapshot_file: String,
+  pub replay: Option<Replay>,
+  pub recorder: Option<Recorder>,
+  pub phosphor: Option<Phosphor>,
+  pub key_map: KeyMap,
+  pub history: bool,
+}
+
+pub fn parse_interactive(
+  flags: &BTreeMap<String, String>,
+  memory_image_file: &str,
+) -> Result<InteractiveOptions, Error> {
+  // snapshots are saved next to the memory image unless `--snapshot` is given
+  Ok(InteractiveOptions {
+    clock_speed: parse_clock_speed(flags)?,
+    debug_info: load_debug_info(flags)?,
+    snapshot_file: match flags.get("snapshot") {
+      Some(snapshot_file) => snapshot_file.clone(),
+      None => format!("{}.snap", memory_image_file),
+    },
+    replay: load_replay(flags)?,
+    recorder: create_recorder(flags)?,
+    phosphor: parse_phosphor(flags)?,
+    key_map: load_key_map(flags)?,
+    history: flags.contains_key("history"),
+  })
+}
+
+struct Peer<MC> {
+  mc: MC,
+  stdin: VecDeque<u8>,
+  stdout: VecDeque<u8>,
+  display: [u8; DISPLAY_BUFFER_LEN],
+  phosphor: Option<Phosphor>,
+  clocks: u128,
+  lag: i128, // clocks the peer is behind the first machine by
+  trap: Option<TickTrap>,
+}
+
+pub fn execute<MC: std::fmt::Display + Tickable>(
+  mut mc: MC,
+  peers: Vec<MC>, // machines linked to `mc`, run alongside it
+  options: InteractiveOptions,
+  snapshot: Option<Snapshot>,
+) -> Result<(), Error> {
+  let InteractiveOptions {
+    clock_speed: throttle,
+    debug_info,
+    snapshot_file,
+    mut replay,
+    mut recorder,
+    mut phosphor,
+    key_map,
+    history,
+  } = options;
+  let snapshot_file = snapshot_file.as_str();
+
   let mut current_clocks = 0;
+  let mut total_clocks = 0;
+  let mut clock_speed = throttle.unwrap_or(DEFAULT_CLOCK_SPEED); // measured clock speed when unthrottled
   let mut initial_time = std::time::Instant::now();
   let mut next_call_clocks = 0;
   let mut next_stdin_clocks = 0;
@@ -74,27 +1392,86 @@ pub fn execute<MC: std::fmt::Display + Tickable>(mut mc: MC, clock_speed: u128)
   let mut status_line = "".to_string();
   let mut debug_mode = false;
   let mut show_state = false;
+  let mut halted = false;
+  let mut mc_halted = false; // `mc` alone, while linked machines may still be running
+  let mut debugger = Debugger::new(history);
+  let mut status_timestamp: Option<std::time::Instant> = None;
 
   let mut stdin = VecDeque::new();
   let mut stdout = VecDeque::new();
   let mut display = [0x00; DISPLAY_BUFFER_LEN];
+  let mut focus = 0; // machine receiving keyboard input, `mc` first
 
   mc.reset(&mut stdin, &mut stdout, &mut display, &mut 0x00);
+  let mut peers: Vec<Peer<MC>> = (peers.into_iter())
+    .map(|mut mc| {
+      let (mut stdin, mut stdout) = (VecDeque::new(), VecDeque::new());
+      let mut display = [0x00; DISPLAY_BUFFER_LEN];
+      mc.reset(&mut stdin, &mut stdout, &mut display, &mut 0x00);
+      Peer {
+        mc,
+        stdin,
+        stdout,
+        display,
+        phosphor: phosphor.clone(),
+        clocks: 0,
+        lag: 0,
+        trap: None,
+      }
+    })
+    .collect();
+  if let Some(snapshot) = snapshot {
+    // buttons held down in the snapshot are pressed anew
+    for (index, timestamp) in controller_timestamps.iter_mut().enumerate() {
+      if snapshot.controller & 1 << index != 0x00 {
+        *timestamp = Some(std::time::Instant::now());
+      }
+    }
+    total_clocks = snapshot.apply(&mut mc, &mut stdin, &mut stdout, &mut display);
+  }
+
+  macro_rules! save_snapshot {
+    ($controller:expr) => {{
+      let snapshot = Snapshot::take(&mc, &stdin, &stdout, &display, $controller, total_clocks);
+      status_line = match snapshot.save(snapshot_file) {
+        Ok(()) => format!("Saved snapshot to '{}'", snapshot_file),
+        Err(error) => error.to_string(),
+      };
+      status_timestamp = Some(std::time::Instant::now());
+    }};
+  }
+
+  macro_rules! record {
+    ($input:expr) => {{
+      let recorded = (recorder.as_mut()).map(|recorder| recorder.record(total_clocks, $input));
+      if let Some(Err(error)) = recorded {
+        recorder = None;
+        status_line = error.to_string();
+        status_timestamp = Some(std::time::Instant::now());
+      }
+    }};
+  }
 
   // this call will switch the termital to raw mode
-  let input_channel = spawn_input_channel();
+  let input_stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+  let (input_channel, input_thread) = spawn_input_channel(input_stop.clone());
 
   loop {
-    let mut controller = controller_timestamps
+    let pressed = controller_timestamps
       .iter()
       .enumerate()
       .fold(0x00, |acc, (index, timestamp)| {
         acc | ((timestamp.is_some() as u8) << index)
       });
+    let mut controller = if focus == 0 { pressed } else { 0x00 };
 
     // call `std::Instant::now()` at most 1000 times per second
     if next_call_clocks <= current_clocks || debug_mode {
-      next_call_clocks += if debug_mode { 0 } else { clock_speed / 1000 };
+      next_call_clocks += if debug_mode {
+        0
+      } else {
+        (clock_speed / 1000).max(1)
+      };
 
       let timestamp_threshold = std::time::Duration::from_millis(200);
       controller_timestamps = controller_timestamps
@@ -107,73 +1484,95 @@ pub fn execute<MC: std::fmt::Display + Tickable>(mut mc: MC, clock_speed: u128)
         .unwrap();
 
       let realtime = std::cmp::max(initial_time.elapsed().as_millis(), 1); // prevent division by zero
+      let effective_speed = 1000 * current_clocks / realtime;
       let realtime_offset = (1000 * current_clocks / clock_speed) as i128 - realtime as i128;
       let realtime_ratio = realtime_offset as f64 / realtime as f64;
-      std::thread::sleep(std::time::Duration::from_millis(
-        std::cmp::max(realtime_offset, 0) as u64,
-      ));
+      match throttle {
+        Some(_) => std::thread::sleep(std::time::Duration::from_millis(std::cmp::max(
+          realtime_offset,
+          0,
+        ) as u64)),
+        None => clock_speed = std::cmp::max(effective_speed, 1000),
+      }
 
-      if !debug_mode {
+      // leave messages on the status line for a second
+      let status_threshold = std::time::Duration::from_millis(1000);
+      if !debug_mode && status_timestamp.is_none_or(|t| t.elapsed() >= status_threshold) {
         let realtime_tolerance = 0.01;
-        status_line = if -realtime_ratio > realtime_tolerance {
-          format!("Execution behind by {:.0}%", -realtime_ratio * 100.0)
+        let effective = render_frequency(effective_speed);
+        status_line = if throttle.is_none() {
+          format!("Execution unthrottled at {}", effective)
+        } else if -realtime_ratio > realtime_tolerance {
+          format!(
+            "Execution behind by {:.0}% at {}",
+            -realtime_ratio * 100.0,
+            effective
+          )
         } else if realtime_ratio > realtime_tolerance {
-          format!("Execution ahead by {:.0}%", realtime_ratio * 100.0)
+          format!(
+            "Execution ahead by {:.0}% at {}",
+            realtime_ratio * 100.0,
+            effective
+          )
         } else {
-          format!("Execution on time")
+          format!("Execution on time at {}", effective)
         };
       }
     }
 
     // read input at most 60 times per second
-    if next_stdin_clocks <= current_clocks || debug_mode {
-      next_stdin_clocks += if debug_mode { 0 } else { clock_speed / 60 };
+    // keys are read by the command line in debug mode
+    if next_stdin_clocks <= current_clocks && !debug_mode {
+      next_stdin_clocks += (clock_speed / 60).max(1);
 
       'until_empty: loop {
         use std::sync::mpsc::TryRecvError;
         match input_channel.try_recv() {
-          Ok(console::Key::Del) => {
-            stdout = VecDeque::new();
-          }
-
-          Ok(console::Key::Tab) => {
-            show_state = !show_state;
-          }
-
-          Ok(console::Key::Escape) => {
-            debug_mode = !debug_mode;
-            status_line = "Force debug".to_string();
-          }
-
           Ok(key) => {
-            let keys = [
-              console::Key::ArrowUp,
-              console::Key::ArrowDown,
-              console::Key::ArrowLeft,
-              console::Key::ArrowRight,
-              console::Key::PageUp,
-              console::Key::PageDown,
-              console::Key::Home,
-              console::Key::End,
-            ];
-
-            controller_timestamps = keys
-              .iter()
-              .map(|k| (k == &key).then_some(std::time::Instant::now()))
-              .zip(controller_timestamps.iter())
-              .map(|(next, curr)| next.or(*curr))
-              .collect::<Vec<_>>()
-              .try_into()
-              .unwrap();
-
-            stdin.extend(match key {
-              console::Key::Char(c) => vec![c as u8],
-              console::Key::Backspace => vec![0x08],
-              console::Key::Enter => vec![0x0A],
-              console::Key::Tab => vec![0x09],
-              console::Key::Del => vec![0x7F],
-              _ => vec![],
-            });
+            for action in key_map.actions(&key) {
+              match action {
+                KeyAction::ClearStdout => match focus {
+                  0 => stdout = VecDeque::new(),
+                  index => peers[index - 1].stdout = VecDeque::new(),
+                },
+                KeyAction::ToggleState => show_state = !show_state,
+                KeyAction::ToggleDebug => {
+                  debug_mode = !debug_mode;
+                  status_line = "Force debug".to_string();
+                }
+                KeyAction::SaveSnapshot => save_snapshot!(controller),
+                KeyAction::SwitchMachine => {
+                  focus = (focus + 1) % (peers.len() + 1);
+                  controller_timestamps = [None; 8];
+                }
+                KeyAction::Controller(index) => {
+                  controller_timestamps[index as usize] = Some(std::time::Instant::now())
+                }
+                KeyAction::Stdin => {
+                  let bytes = match key {
+                    console::Key::Char(c) => vec![c as u8],
+                    console::Key::Backspace => vec![0x08],
+                    console::Key::Enter => vec![0x0A],
+                    console::Key::Tab => vec![0x09],
+                    console::Key::Del => vec![0x7F],
+                    _ => vec![],
+                  };
+
+                  // keyboard input is ignored while replaying
+                  if replay.is_none() {
+                    for c in bytes {
+                      match focus {
+                        0 => {
+                          record!(Input::Stdin(c));
+                          stdin.push_back(c);
+                        }
+                        index => peers[index - 1].stdin.push_back(c),
+                      }
+                    }
+                  }
+                }
+              }
+            }
           }
 
           Err(TryRecvError::Empty) => break 'until_empty,
@@ -183,21 +1582,30 @@ pub fn execute<MC: std::fmt::Display + Tickable>(mut mc: MC, clock_speed: u128)
     }
 
     // write output at most 60 times per second
-    if next_stdout_clocks <= current_clocks || debug_mode {
-      next_stdout_clocks += if debug_mode { 0 } else { clock_speed / 60 };
+    if next_stdout_clocks <= current_clocks || debug_mode || halted {
+      next_stdout_clocks += if debug_mode {
+        0
+      } else {
+        (clock_speed / 60).max(1)
+      };
 
-      stdout = stdout
+      // only the output of the machine receiving keyboard input is shown
+      let focused_stdout = match focus {
+        0 => &mut stdout,
+        index => &mut peers[index - 1].stdout,
+      };
+      *focused_stdout = std::mem::take(focused_stdout)
         .into_iter()
         .filter(|c| *c <= 0x7F) // outside ASCII
         .filter(|c| *c != 0x00) // NUL
         .collect::<VecDeque<_>>();
-      let stdout_string = stdout
+      let stdout_string = focused_stdout
         .iter()
         .map(|c| *c as char)
         .collect::<String>()
         .replace("\n", "\r\n")
         .replace("\x08", "\x08 \x08");
-      stdout = stdout
+      *focused_stdout = std::mem::take(focused_stdout)
         .into_iter()
         .filter(|c| *c != 0x07) // BEL
         .collect::<VecDeque<_>>();
@@ -208,41 +1616,117 @@ pub fn execute<MC: std::fmt::Display + Tickable>(mut mc: MC, clock_speed: u128)
 
       print!("{}\r\n", status_line);
       if show_state || debug_mode {
-        print!("Clocks: {}\r\n", current_clocks);
+        print!("Clocks: {}\r\n", total_clocks);
         print!("\r\n");
         print!("{}", mc);
-      } else {
+        if let Some(debug_info) = &debug_info {
+          print!("\r\n");
+          print!("{}", debug_info.render(mc.ip()));
+        }
+        if debug_mode {
+          print!("\r\n");
+          print!("{}", debugger);
+        }
+      } else if peers.is_empty() {
         print!("\r\n");
-        print!("{}", render_display(&display));
+        match &mut phosphor {
+          Some(phosphor) => print!("{}", render_phosphor(&phosphor.levels(total_clocks))),
+          None => print!("{}", render_display(&display)),
+        }
         print!("{}", render_controller(&controller));
+      } else {
+        // linked machines are shown side by side, the one receiving keyboard input marked
+        let mut columns = vec![];
+        let trap = mc_halted.then_some(TickTrap::Halted);
+        let machines = std::iter::once((&display, &mut phosphor, total_clocks, trap)).chain(
+          peers
+            .iter_mut()
+            .map(|peer| (&peer.display, &mut peer.phosphor, peer.clocks, peer.trap)),
+        );
+        for (index, (display, phosphor, clocks, trap)) in machines.enumerate() {
+          let mut column = format!(
+            "{} Machine {}{}\r\n",
+            if index == focus { ">" } else { " " },
+            index + 1,
+            trap.map(|trap| format!(": {}", trap)).unwrap_or_default()
+          );
+          column += &match phosphor {
+            Some(phosphor) => render_phosphor(&phosphor.levels(clocks)),
+            None => render_display(display),
+          };
+          column += &render_controller(&if index == focus { pressed } else { 0x00 });
+          columns.push(column);
+        }
+        print!("{}", render_columns(&columns));
       }
       print!("\r\n");
       print!("{}", stdout_string);
-      use std::io::Write;
       std::io::stdout().flush().unwrap();
     }
 
+    if halted {
+      break;
+    }
+
     if debug_mode {
-      'until_valid: loop {
-        match input_channel.try_recv() {
-          Ok(console::Key::Del) => {
+      let step = 'until_valid: loop {
+        let key = input_channel.recv();
+        // keys not bound to a debugger action edit the command line
+        let action = (key.iter())
+          .flat_map(|key| key_map.actions(key))
+          .find(|action| !matches!(action, KeyAction::Controller(_) | KeyAction::Stdin));
+        match action {
+          Some(KeyAction::ClearStdout) => {
             stdout = VecDeque::new();
-            break 'until_valid;
+            break 'until_valid true;
           }
 
-          Ok(console::Key::Tab) => {
+          Some(KeyAction::ToggleState) => {
             status_line = "Single stepped".to_string();
-            break 'until_valid;
+            break 'until_valid true;
           }
 
-          Ok(console::Key::Escape) => {
+          Some(KeyAction::ToggleDebug) => {
             debug_mode = !debug_mode;
-            break 'until_valid;
+            break 'until_valid true;
+          }
+
+          Some(KeyAction::SaveSnapshot) => {
+            save_snapshot!(controller);
+            break 'until_valid false;
           }
 
-          _ => continue 'until_valid,
+          _ => (),
         }
-      }
+
+        match key {
+          Ok(console::Key::Char(c)) => {
+            debugger.command.push(c);
+            break 'until_valid false;
+          }
+
+          Ok(console::Key::Backspace) => {
+            debugger.command.pop();
+            break 'until_valid false;
+          }
+
+          Ok(console::Key::Enter) => {
+            let command = std::mem::take(&mut debugger.command);
+            let rewind = (&mut stdin, &mut display, &mut total_clocks);
+            match debugger.execute(&command, &mut mc, rewind) {
+              Ok(resume) => debug_mode = !resume,
+              Err(error) => status_line = error.to_string(),
+            }
+            if let Some(status) = debugger.status.take() {
+              status_line = status;
+            }
+            break 'until_valid !debug_mode;
+          }
+
+          Ok(_) => continue 'until_valid,
+          Err(_) => panic!("Channel disconnected"),
+        }
+      };
 
       // conceptually hacky but does the job
       initial_time = std::time::Instant::now();
@@ -250,37 +1734,104 @@ pub fn execute<MC: std::fmt::Display + Tickable>(mut mc: MC, clock_speed: u128)
       next_call_clocks = 0;
       next_stdin_clocks = 0;
       next_stdout_clocks = 0;
+
+      // editing the command line only requires a redraw
+      if !step {
+        continue;
+      }
     }
 
-    match mc.tick(&mut stdin, &mut stdout, &mut display, &mut controller) {
-      Ok(clocks) => {
-        current_clocks += clocks;
-      }
-      Err(tick_trap) => {
-        debug_mode = true;
-        status_line = match tick_trap {
-          TickTrap::MicrocodeFault => format!("Microcode fault"),
-          TickTrap::BusContention => format!("Bus contention"),
-          TickTrap::IllegalOpcode => format!("Illegal opcode"),
-          TickTrap::DebugRequest => format!("Debug request"),
+    if let Some(replay) = &mut replay {
+      controller = replay.feed(total_clocks, &mut stdin);
+    }
+    record!(Input::Controller(controller));
+
+    // once `mc` halts, linked machines still running are clocked one clock at a time
+    let clocks = match mc_halted {
+      true => 1,
+      false => {
+        debugger.observe(&mc, &stdin);
+        match mc.tick(&mut stdin, &mut stdout, &mut display, &mut controller) {
+          Ok(clocks) => {
+            total_clocks += clocks;
+            if let Some(phosphor) = &mut phosphor {
+              phosphor.update(total_clocks, &display);
+            }
+            if let Some(reason) = debugger.check(&mc, &stdin, clocks) {
+              debug_mode = true;
+              status_line = reason;
+            }
+            clocks
+          }
+          Err(TickTrap::Halted) => {
+            mc_halted = true;
+            0
+          }
+          Err(tick_trap) => {
+            debug_mode = true;
+            status_line = tick_trap.to_string();
+            0
+          }
         }
       }
     };
+    current_clocks += clocks;
+
+    // linked machines keep up with `mc` and stop for good when they trap
+    for (index, peer) in peers.iter_mut().enumerate() {
+      let mut controller = if focus == index + 1 { pressed } else { 0x00 };
+      peer.lag += clocks as i128;
+      while peer.lag > 0 && peer.trap.is_none() {
+        let (stdin, stdout, display) = (&mut peer.stdin, &mut peer.stdout, &mut peer.display);
+        match peer.mc.tick(stdin, stdout, display, &mut controller) {
+          Ok(clocks) => {
+            peer.lag -= clocks as i128;
+            peer.clocks += clocks;
+            if let Some(phosphor) = &mut peer.phosphor {
+              phosphor.update(peer.clocks, &peer.display);
+            }
+          }
+          Err(tick_trap) => peer.trap = Some(tick_trap),
+        }
+      }
+    }
+
+    // execution ends once every machine has halted or trapped
+    if mc_halted && peers.iter().all(|peer| peer.trap.is_some()) {
+      halted = true;
+      status_line = format!(
+        "Halted after {} clocks, press any key to exit",
+        total_clocks
+      );
+    }
   }
 
+  // the input thread restores the terminal once the next key is read
+  input_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+  input_thread.join().unwrap();
+  print!("\r\n");
+  return (peers.iter_mut())
+    .map(|peer| peer.mc.bus_mut().flush())
+    .fold(mc.bus_mut().flush(), Result::and);
+
+  use std::sync::atomic::{AtomicBool, Ordering};
   use std::sync::mpsc;
   use std::sync::mpsc::Receiver;
-  fn spawn_input_channel() -> Receiver<console::Key> {
+  use std::sync::Arc;
+  use std::thread::JoinHandle;
+  fn spawn_input_channel(stop: Arc<AtomicBool>) -> (Receiver<console::Key>, JoinHandle<()>) {
     let stdout = console::Term::stdout();
 
     let (tx, rx) = mpsc::channel::<console::Key>();
-    std::thread::spawn(move || loop {
-      if let Ok(key) = stdout.read_key() {
-        tx.send(key).unwrap();
+    let thread = std::thread::spawn(move || {
+      while !stop.load(Ordering::Relaxed) {
+        if let Ok(key) = stdout.read_key() {
+          tx.send(key).unwrap();
+        }
       }
     });
 
-    rx
+    (rx, thread)
   }
 }
 
@@ -311,6 +1862,11 @@ pub fn render_memory(memory: &[u8; MEM_SIZE], ip: u8, sp: u8, cf: bool) -> Strin
   fmt
 }
 
+pub fn display_pixel(display: &[u8; DISPLAY_BUFFER_LEN], x: u8, y: u8) -> bool {
+  let address: u8 = (x >> 0x03) | (y << 0x01);
+  display[address as usize] >> (0x07 - (x & 0x07)) & 0x01 != 0x00
+}
+
 pub fn render_display(display: &[u8; DISPLAY_BUFFER_LEN]) -> String {
   let mut fmt = "".to_string();
 
@@ -320,15 +1876,13 @@ pub fn render_display(display: &[u8; DISPLAY_BUFFER_LEN]) -> String {
   let col_left: &str = " ";
   let col_right: &str = " ";
 
-  fmt += &line_top;
+  fmt += line_top;
   for y in (0..0x10).step_by(2) {
-    fmt += &col_left;
+    fmt += col_left;
     for x in 0..0x10 {
       let mut pixel_pair = 0;
       for y2 in 0..2 {
-        let address: u8 = (x >> 0x03) | ((y + y2) << 0x01);
-        let pixel = display[address as usize] >> (0x07 - (x & 0x07)) & 0x01;
-        pixel_pair |= pixel << y2;
+        pixel_pair |= (display_pixel(display, x, y + y2) as u8) << y2;
       }
       fmt += match pixel_pair {
         0b00 => " ",
@@ -338,15 +1892,147 @@ pub fn render_display(display: &[u8; DISPLAY_BUFFER_LEN]) -> String {
         _ => unreachable!(),
       };
     }
-    fmt += &col_right;
+    fmt += col_right;
+    fmt += "\r\n";
+  }
+  fmt += line_bottom;
+  fmt += "\r\n";
+
+  fmt
+}
+
+pub type Levels = [u8; 0x100]; // brightness of every pixel, row by row
+
+pub fn display_levels(display: &[u8; DISPLAY_BUFFER_LEN]) -> Levels {
+  std::array::from_fn(|index| {
+    match display_pixel(display, index as u8 % 0x10, index as u8 / 0x10) {
+      true => 0xFF,
+      false => 0x00,
+    }
+  })
+}
+
+pub fn is_gray(levels: &Levels) -> bool {
+  levels.iter().any(|level| !matches!(level, 0x00 | 0xFF))
+}
+
+#[derive(Clone)]
+pub struct Phosphor {
+  decay: u128,                       // time constant of the exponential decay, in clocks
+  clocks: u128,                      // clocks `brightness` is up to date with
+  display: [u8; DISPLAY_BUFFER_LEN], // display as of `clocks`
+  brightness: [f64; 0x100],          // between 0.0 and 1.0, row by row
+}
+
+impl Phosphor {
+  pub fn new(decay: u128) -> Phosphor {
+    Phosphor {
+      decay,
+      clocks: 0,
+      display: [0x00; DISPLAY_BUFFER_LEN],
+      brightness: [0.0; 0x100],
+    }
+  }
+
+  pub fn update(&mut self, clocks: u128, display: &[u8; DISPLAY_BUFFER_LEN]) {
+    // the display is constant between changes, so spans between changes are integrated at once
+    if *display != self.display {
+      self.integrate(clocks);
+      self.display = *display;
+    }
+  }
+
+  pub fn levels(&mut self, clocks: u128) -> Levels {
+    self.integrate(clocks);
+    self
+      .brightness
+      .map(|brightness| (brightness * 255.0).round() as u8)
+  }
+
+  fn integrate(&mut self, clocks: u128) {
+    // every pixel decays exponentially towards fully lit or fully unlit
+    let elapsed = clocks.saturating_sub(self.clocks);
+    let retained = (-(elapsed as f64) / self.decay as f64).exp();
+    for (index, brightness) in self.brightness.iter_mut().enumerate() {
+      let target = match display_pixel(&self.display, index as u8 % 0x10, index as u8 / 0x10) {
+        true => 1.0,
+        false => 0.0,
+      };
+      *brightness = target + (*brightness - target) * retained;
+    }
+    self.clocks = self.clocks.max(clocks);
+  }
+}
+
+pub fn render_phosphor(levels: &Levels) -> String {
+  let mut fmt = "".to_string();
+
+  // pixels are two characters wide to remain square, as shades cannot be split into half blocks
+  // https://en.wikipedia.org/wiki/Block_Elements
+  let line_top: &str = "\u{25aa}                                \u{25aa}\r\n";
+  let line_bottom: &str = "\u{25aa}                                \u{25aa}\r\n";
+  let col_left: &str = " ";
+  let col_right: &str = " ";
+
+  fmt += line_top;
+  for row in levels.chunks(0x10) {
+    fmt += col_left;
+    for level in row {
+      fmt += match level {
+        0x00..0x20 => "  ",
+        0x20..0x60 => "\u{2591}\u{2591}",
+        0x60..0xA0 => "\u{2592}\u{2592}",
+        0xA0..0xE0 => "\u{2593}\u{2593}",
+        0xE0..=0xFF => "\u{2588}\u{2588}",
+      };
+    }
+    fmt += col_right;
     fmt += "\r\n";
   }
-  fmt += &line_bottom;
+  fmt += line_bottom;
   fmt += "\r\n";
 
   fmt
 }
 
+pub fn render_columns(columns: &[String]) -> String {
+  // place multiline strings side by side
+  let columns: Vec<Vec<&str>> = (columns.iter())
+    .map(|column| column.split("\r\n").collect())
+    .collect();
+  let widths: Vec<usize> = (columns.iter())
+    .map(|lines| {
+      lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+    })
+    .collect();
+  let height = columns.iter().map(Vec::len).max().unwrap_or(0);
+
+  let mut fmt = "".to_string();
+  for y in 0..height {
+    let line = (columns.iter().zip(widths.iter()))
+      .map(|(lines, width)| format!("{:width$}", lines.get(y).unwrap_or(&""), width = width))
+      .collect::<Vec<String>>()
+      .join("  ");
+    fmt += line.trim_end();
+    if y + 1 < height {
+      fmt += "\r\n";
+    }
+  }
+  fmt
+}
+
+pub fn render_frequency(hertz: u128) -> String {
+  match hertz {
+    0..1000 => format!("{} Hz", hertz),
+    1000..1000000 => format!("{:.2} kHz", hertz as f64 / 1000.0),
+    _ => format!("{:.2} MHz", hertz as f64 / 1000000.0),
+  }
+}
+
 pub fn render_controller(controller: &u8) -> String {
   let mut fmt = "".to_string();
 
@@ -388,8 +2074,8 @@ impl From<u16> for ControlWord {
   fn from(control_word: u16) -> Self {
     let control_word = {
       let mut slice = [0x00; 16];
-      for i in 0..slice.len() {
-        slice[i] = (control_word >> i) as u8 & 1;
+      for (i, bit) in slice.iter_mut().enumerate() {
+        *bit = (control_word >> i) as u8 & 1;
       }
       slice.reverse();
       slice
@@ -409,10 +2095,11 @@ impl From<u16> for ControlWord {
   }
 }
 
-impl Into<u16> for ControlWord {
-  fn into(self) -> u16 {
-    let control_word =
-      unsafe { std::mem::transmute::<ControlWord, [u8; std::mem::size_of::<ControlWord>()]>(self) };
+impl From<ControlWord> for u16 {
+  fn from(control_word: ControlWord) -> Self {
+    let control_word = unsafe {
+      std::mem::transmute::<ControlWord, [u8; std::mem::size_of::<ControlWord>()]>(control_word)
+    };
 
     control_word
       .iter()
@@ -436,10 +2123,35 @@ pub fn result_into_u16(result: Result<ControlWord, TickTrap>) -> u16 {
     Err(TickTrap::BusContention) => BUS_CONTENTION_SENTINEL,
     Err(TickTrap::IllegalOpcode) => ILLEGAL_OPCODE_SENTINEL,
     Err(TickTrap::DebugRequest) => DEBUG_REQUEST_SENTINEL,
+    Err(
+      TickTrap::StackOverflow
+      | TickTrap::CodeCollision
+      | TickTrap::UninitializedRead
+      | TickTrap::CodeModification
+      | TickTrap::DataExecution,
+    ) => panic!("Emulator traps have no control word representation"),
+    Err(TickTrap::Halted) => panic!("Halt has no control word representation"),
     Ok(control_word) => control_word.into(),
   }
 }
 
+impl std::fmt::Display for TickTrap {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TickTrap::MicrocodeFault => write!(f, "Microcode fault"),
+      TickTrap::BusContention => write!(f, "Bus contention"),
+      TickTrap::IllegalOpcode => write!(f, "Illegal opcode"),
+      TickTrap::DebugRequest => write!(f, "Debug request"),
+      TickTrap::StackOverflow => write!(f, "Stack overflow"),
+      TickTrap::CodeCollision => write!(f, "Code collision"),
+      TickTrap::UninitializedRead => write!(f, "Read of uninitialized stack slot"),
+      TickTrap::CodeModification => write!(f, "Write into executed code"),
+      TickTrap::DataExecution => write!(f, "Execution of written data"),
+      TickTrap::Halted => write!(f, "Halted"),
+    }
+  }
+}
+
 impl std::fmt::Display for Signal {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
@@ -449,7 +2161,7 @@ impl std::fmt::Display for Signal {
   }
 }
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq, Hash)]
 pub struct File(pub PathBuf);
 
 #[derive(Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -464,7 +2176,7 @@ pub struct Macro(pub String);
 #[derive(Clone, Eq, PartialEq)]
 pub struct Error(pub String);
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Pos(pub File, pub usize, pub usize);
 
 #[derive(Clone, Eq, PartialEq)]
@@ -696,7 +2408,7 @@ pub fn instruction_to_opcode(instruction: Result<Instruction, u8>) -> u8 {
   }
 
   match instruction {
-    Ok(Instruction::Psh(imm)) => 0b00000000 | encode_imm(imm),
+    Ok(Instruction::Psh(imm)) => encode_imm(imm),
     Ok(Instruction::Add(size)) => 0b10000000 | encode_size(size),
     Ok(Instruction::Sub(size)) => 0b10000100 | encode_size(size),
     Ok(Instruction::Iff(size)) => 0b10010000 | encode_size(size),
@@ -747,51 +2459,51 @@ pub fn token_to_mnemonic(token: Token) -> Mnemonic {
     Token::LabelRef(Label::Global(identifier)) => Mnemonic(format!(":{}", identifier)),
     Token::MacroDef(Macro(r#macro)) => Mnemonic(format!("{}!", r#macro)),
     Token::MacroRef(Macro(r#macro)) => Mnemonic(format!("!{}", r#macro)),
-    Token::AtError => Mnemonic(format!("@error")),
-    Token::AtConst => Mnemonic(format!("@const")),
-    Token::AtData => Mnemonic(format!("@data")),
-    Token::AtDyn => Mnemonic(format!("@dyn")),
-    Token::AtOrg => Mnemonic(format!("@org")),
+    Token::AtError => Mnemonic("@error".to_string()),
+    Token::AtConst => Mnemonic("@const".to_string()),
+    Token::AtData => Mnemonic("@data".to_string()),
+    Token::AtDyn => Mnemonic("@dyn".to_string()),
+    Token::AtOrg => Mnemonic("@org".to_string()),
     Token::AtDD(value) => Mnemonic(format!("@{:02X}", value)),
     Token::XXX(value) => Mnemonic(format!("x{:02X}", value)),
-    Token::Add => Mnemonic(format!("add")),
+    Token::Add => Mnemonic("add".to_string()),
     Token::AdS(size) => Mnemonic(format!("ad{:01X}", size.get())),
-    Token::Sub => Mnemonic(format!("sub")),
+    Token::Sub => Mnemonic("sub".to_string()),
     Token::SuS(size) => Mnemonic(format!("su{:01X}", size.get())),
-    Token::Iff => Mnemonic(format!("iff")),
+    Token::Iff => Mnemonic("iff".to_string()),
     Token::IfS(size) => Mnemonic(format!("if{:01X}", size.get())),
-    Token::Swp => Mnemonic(format!("swp")),
+    Token::Swp => Mnemonic("swp".to_string()),
     Token::SwS(size) => Mnemonic(format!("sw{:01X}", size.get())),
-    Token::Rot => Mnemonic(format!("rot")),
+    Token::Rot => Mnemonic("rot".to_string()),
     Token::RoS(size) => Mnemonic(format!("ro{:01X}", size.get())),
-    Token::Orr => Mnemonic(format!("orr")),
+    Token::Orr => Mnemonic("orr".to_string()),
     Token::OrS(size) => Mnemonic(format!("or{:01X}", size.get())),
-    Token::And => Mnemonic(format!("and")),
+    Token::And => Mnemonic("and".to_string()),
     Token::AnS(size) => Mnemonic(format!("an{:01X}", size.get())),
-    Token::Xor => Mnemonic(format!("xor")),
+    Token::Xor => Mnemonic("xor".to_string()),
     Token::XoS(size) => Mnemonic(format!("xo{:01X}", size.get())),
-    Token::Xnd => Mnemonic(format!("xnd")),
+    Token::Xnd => Mnemonic("xnd".to_string()),
     Token::XnS(size) => Mnemonic(format!("xn{:01X}", size.get())),
-    Token::Inc => Mnemonic(format!("inc")),
-    Token::Dec => Mnemonic(format!("dec")),
-    Token::Neg => Mnemonic(format!("neg")),
-    Token::Shl => Mnemonic(format!("shl")),
-    Token::Shr => Mnemonic(format!("shr")),
-    Token::Not => Mnemonic(format!("not")),
-    Token::Buf => Mnemonic(format!("buf")),
+    Token::Inc => Mnemonic("inc".to_string()),
+    Token::Dec => Mnemonic("dec".to_string()),
+    Token::Neg => Mnemonic("neg".to_string()),
+    Token::Shl => Mnemonic("shl".to_string()),
+    Token::Shr => Mnemonic("shr".to_string()),
+    Token::Not => Mnemonic("not".to_string()),
+    Token::Buf => Mnemonic("buf".to_string()),
     Token::LdO(ofst) => Mnemonic(format!("ld{:01X}", ofst.get())),
     Token::StO(ofst) => Mnemonic(format!("st{:01X}", ofst.get())),
-    Token::Lda => Mnemonic(format!("lda")),
-    Token::Sta => Mnemonic(format!("sta")),
-    Token::Ldi => Mnemonic(format!("ldi")),
-    Token::Sti => Mnemonic(format!("sti")),
-    Token::Lds => Mnemonic(format!("lds")),
-    Token::Sts => Mnemonic(format!("sts")),
-    Token::Clc => Mnemonic(format!("clc")),
-    Token::Sec => Mnemonic(format!("sec")),
-    Token::Flc => Mnemonic(format!("flc")),
-    Token::Nop => Mnemonic(format!("nop")),
-    Token::Pop => Mnemonic(format!("pop")),
+    Token::Lda => Mnemonic("lda".to_string()),
+    Token::Sta => Mnemonic("sta".to_string()),
+    Token::Ldi => Mnemonic("ldi".to_string()),
+    Token::Sti => Mnemonic("sti".to_string()),
+    Token::Lds => Mnemonic("lds".to_string()),
+    Token::Sts => Mnemonic("sts".to_string()),
+    Token::Clc => Mnemonic("clc".to_string()),
+    Token::Sec => Mnemonic("sec".to_string()),
+    Token::Flc => Mnemonic("flc".to_string()),
+    Token::Nop => Mnemonic("nop".to_string()),
+    Token::Pop => Mnemonic("pop".to_string()),
   }
 }
 
@@ -856,20 +2568,20 @@ pub fn mnemonic_to_token(mnemonic: Mnemonic) -> Option<Token> {
     "nop" => Some(Token::Nop),
     "pop" => Some(Token::Pop),
     _ if mnemonic.len() == 3 => match mnemonic.split_at(2) {
-      ("ad", hex) => parse_hex(&hex).and_then(Size::new).map(Token::AdS),
-      ("su", hex) => parse_hex(&hex).and_then(Size::new).map(Token::SuS),
-      ("if", hex) => parse_hex(&hex).and_then(Size::new).map(Token::IfS),
-      ("sw", hex) => parse_hex(&hex).and_then(Size::new).map(Token::SwS),
-      ("ro", hex) => parse_hex(&hex).and_then(Size::new).map(Token::RoS),
-      ("or", hex) => parse_hex(&hex).and_then(Size::new).map(Token::OrS),
-      ("an", hex) => parse_hex(&hex).and_then(Size::new).map(Token::AnS),
-      ("xo", hex) => parse_hex(&hex).and_then(Size::new).map(Token::XoS),
-      ("xn", hex) => parse_hex(&hex).and_then(Size::new).map(Token::XnS),
-      ("ld", hex) => parse_hex(&hex).and_then(Ofst::new).map(Token::LdO),
-      ("st", hex) => parse_hex(&hex).and_then(Ofst::new).map(Token::StO),
+      ("ad", hex) => parse_hex(hex).and_then(Size::new).map(Token::AdS),
+      ("su", hex) => parse_hex(hex).and_then(Size::new).map(Token::SuS),
+      ("if", hex) => parse_hex(hex).and_then(Size::new).map(Token::IfS),
+      ("sw", hex) => parse_hex(hex).and_then(Size::new).map(Token::SwS),
+      ("ro", hex) => parse_hex(hex).and_then(Size::new).map(Token::RoS),
+      ("or", hex) => parse_hex(hex).and_then(Size::new).map(Token::OrS),
+      ("an", hex) => parse_hex(hex).and_then(Size::new).map(Token::AnS),
+      ("xo", hex) => parse_hex(hex).and_then(Size::new).map(Token::XoS),
+      ("xn", hex) => parse_hex(hex).and_then(Size::new).map(Token::XnS),
+      ("ld", hex) => parse_hex(hex).and_then(Ofst::new).map(Token::LdO),
+      ("st", hex) => parse_hex(hex).and_then(Ofst::new).map(Token::StO),
       _ => match mnemonic.split_at(1) {
-        ("@", hex) => parse_hex(&hex).map(Token::AtDD),
-        ("x", hex) => parse_hex(&hex).map(Token::XXX),
+        ("@", hex) => parse_hex(hex).map(Token::AtDD),
+        ("x", hex) => parse_hex(hex).map(Token::XXX),
         _ => None,
       },
     },
@@ -948,6 +2660,46 @@ impl std::fmt::Display for Error {
   }
 }
 
+impl std::fmt::Display for DebugInfo {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    for (address, label) in &self.labels {
+      writeln!(
+        f,
+        "label {:02X} {}",
+        address,
+        token_to_mnemonic(Token::LabelDef(label.clone()))
+      )?;
+    }
+    for (address, r#macro, pos) in &self.origins {
+      writeln!(f, "origin {:02X} {} {}", address, r#macro, pos)?;
+    }
+    for (first, last, region) in &self.regions {
+      writeln!(f, "region {:02X} {:02X} {}", first, last, region)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for Region {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Region::Code => write!(f, "code"),
+      Region::Data => write!(f, "data"),
+      Region::Display => write!(f, "display"),
+      Region::Stack => write!(f, "stack"),
+    }
+  }
+}
+
+impl std::fmt::Display for Input {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Input::Stdin(c) => write!(f, "stdin {:02X}", c),
+      Input::Controller(controller) => write!(f, "controller {:02X}", controller),
+    }
+  }
+}
+
 impl std::fmt::Display for Pos {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     write!(f, "{}:{}:{}", self.0, self.1 + 1, self.2 + 1)
@@ -0,0 +1,378 @@
+use super::*;
+
+#[derive(Default)]
+pub struct Debugger {
+  pub command: String,
+  breakpoints: BTreeSet<u8>,
+  read_watchpoints: BTreeSet<u8>,
+  write_watchpoints: BTreeSet<u8>,
+  sp_condition: Option<(char, u8, bool)>, // comparison, operand, last outcome
+  cf_condition: Option<(bool, bool)>,     // operand, last outcome
+  until: Option<Until>,
+  watch_hit: Option<String>,
+  opcode: Option<u8>, // opcode about to be executed, if at an instruction boundary
+  pub status: Option<String>,
+  record_history: bool, // off unless stepping back is wanted, as it checkpoints every instruction
+  history: VecDeque<Record>,
+  pending: Option<(Record, [u8; MEM_SIZE], Vec<u8>)>, // instruction, memory and state before it
+  stdin_front: Vec<u8>,                               // `stdin` before the last tick
+  stdin_len: usize,
+}
+
+struct Record {
+  ip: u8,
+  opcode: u8,
+  writes: Vec<u8>,         // addresses written to
+  state: Vec<(usize, u8)>, // machine state before, as the bytes that differ from the state after
+  state_len: usize,
+  stdin: Vec<u8>, // bytes consumed
+  clocks: u128,
+}
+
+const HISTORY_LEN: usize = 0x10000;
+
+enum Until {
+  Boundary,       // next instruction boundary
+  Return(u8, u8), // IP reached with SP above operand
+  Unwind(u8),     // `sti` leaving SP above operand
+  Address(u8),    // IP reached
+}
+
+impl Debugger {
+  pub fn new(record_history: bool) -> Debugger {
+    Debugger {
+      record_history,
+      ..Debugger::default()
+    }
+  }
+
+  pub fn execute(
+    &mut self,
+    command: &str,
+    mc: &mut impl Tickable,
+    rewind: (&mut VecDeque<u8>, &mut [u8; DISPLAY_BUFFER_LEN], &mut u128),
+  ) -> Result<bool, Error> {
+    // returns whether execution should resume
+
+    fn parse_address(arg: &str) -> Result<u8, Error> {
+      u8::from_str_radix(arg, 16).map_err(|_| Error(format!("Invalid address '{}'", arg)))
+    }
+
+    fn parse_range(arg: &str) -> Result<Vec<u8>, Error> {
+      match arg.split_once("..") {
+        Some((start, end)) => {
+          let start = parse_address(start)? as usize;
+          let end = usize::from_str_radix(end, 16)
+            .ok()
+            .filter(|end| (start..=MEM_SIZE).contains(end))
+            .ok_or(Error(format!("Invalid range '{}'", arg)))?;
+          Ok((start..end).map(|address| address as u8).collect())
+        }
+        None => Ok(vec![parse_address(arg)?]),
+      }
+    }
+
+    fn toggle(set: &mut BTreeSet<u8>, addresses: Vec<u8>) {
+      match addresses.iter().all(|address| set.contains(address)) {
+        true => set.retain(|address| !addresses.contains(address)),
+        false => set.extend(addresses),
+      }
+    }
+
+    let command: String = command.split_whitespace().collect();
+    let sti = instruction_to_opcode(Ok(Instruction::Sti));
+
+    // conditions only break on a transition from false to true
+    match command.as_str() {
+      "" => (),
+      "sp" => self.sp_condition = None,
+      "cf" => self.cf_condition = None,
+      _ if command.starts_with("sp") => {
+        let comparison = command["sp".len()..].chars().next().unwrap();
+        if !"<>=".contains(comparison) {
+          Err(Error(format!("Invalid comparison '{}'", comparison)))?;
+        }
+        let operand = parse_address(&command["sp".len() + comparison.len_utf8()..])?;
+        let outcome = Debugger::compare(comparison, mc.sp(), operand);
+        self.sp_condition = Some((comparison, operand, outcome));
+      }
+      "cf=0" | "cf=1" => {
+        let operand = command == "cf=1";
+        self.cf_condition = Some((operand, mc.cf() == operand));
+      }
+      "s" => self.until = Some(Until::Boundary),
+      "n" => {
+        // `!call` jumps with its return address right below the jump target
+        let (ip, sp, mem) = (mc.ip(), mc.sp(), mc.mem());
+        let call =
+          mem[ip as usize] == sti && mem[sp.wrapping_add(1) as usize] == ip.wrapping_add(1);
+        self.until = Some(match call {
+          true => Until::Return(ip.wrapping_add(1), sp),
+          false => Until::Boundary,
+        });
+      }
+      "f" => self.until = Some(Until::Unwind(mc.sp())),
+      "c" => self.until = None,
+      "u" => {
+        self.rewind(mc, rewind, 1)?;
+        self.status = Some("Stepped back".to_string());
+      }
+      "x" => {
+        self.breakpoints.clear();
+        self.read_watchpoints.clear();
+        self.write_watchpoints.clear();
+        self.sp_condition = None;
+        self.cf_condition = None;
+      }
+      _ => match command.split_at(command.len().min(1)) {
+        ("b", arg) => toggle(&mut self.breakpoints, vec![parse_address(arg)?]),
+        ("r", arg) => toggle(&mut self.read_watchpoints, parse_range(arg)?),
+        ("w", arg) => toggle(&mut self.write_watchpoints, parse_range(arg)?),
+        ("g", arg) => self.until = Some(Until::Address(parse_address(arg)?)),
+        ("u", arg) => {
+          let address = parse_address(arg)?;
+          let count = self
+            .history
+            .iter()
+            .rev()
+            .position(|record| record.writes.contains(&address))
+            .ok_or(Error(format!("No write to {:02X} in history", address)))?;
+          self.rewind(mc, rewind, count + 1)?;
+          self.status = Some(format!("Rewound to last write of {:02X}", address));
+        }
+        _ => Err(Error(format!("Invalid command '{}'", command)))?,
+      },
+    }
+
+    Ok(matches!(command.as_str(), "s" | "n" | "f" | "c") || command.starts_with('g'))
+  }
+
+  fn rewind(
+    &mut self,
+    mc: &mut impl Tickable,
+    (stdin, display, clocks): (&mut VecDeque<u8>, &mut [u8; DISPLAY_BUFFER_LEN], &mut u128),
+    count: usize,
+  ) -> Result<(), Error> {
+    // undo the last `count` instructions. output already sent to `stdout` cannot be undone
+
+    if !self.record_history {
+      Err(Error("Stepping back requires --history".to_string()))?;
+    }
+    if !mc.boundary() {
+      Err(Error(
+        "Cannot step back from within an instruction".to_string(),
+      ))?;
+    }
+    if count > self.history.len() {
+      Err(Error("History exhausted".to_string()))?;
+    }
+
+    // machine state is restored whole, peripherals and microprocessor latches included
+    let mut state = mc.save();
+    for record in self.history.drain(self.history.len() - count..).rev() {
+      state.resize(record.state_len, 0x00);
+      for (index, byte) in record.state {
+        state[index] = byte;
+      }
+      record
+        .stdin
+        .into_iter()
+        .rev()
+        .for_each(|c| stdin.push_front(c));
+      *clocks -= record.clocks;
+    }
+    mc.restore(&state);
+
+    display.copy_from_slice(&mc.mem()[DISPLAY_BUFFER..DISPLAY_BUFFER + DISPLAY_BUFFER_LEN]);
+    self.pending = None;
+    Ok(())
+  }
+
+  fn compare(comparison: char, value: u8, operand: u8) -> bool {
+    match comparison {
+      '<' => value < operand,
+      '>' => value > operand,
+      _ => value == operand,
+    }
+  }
+
+  pub fn observe(&mut self, mc: &impl Tickable, stdin: &VecDeque<u8>) {
+    // note down the opcode about to be executed and the state it will be executed from
+    self.opcode = mc.boundary().then(|| mc.mem()[mc.ip() as usize]);
+    if !self.record_history {
+      return;
+    }
+
+    if let Some(opcode) = self.opcode {
+      let record = Record {
+        ip: mc.ip(),
+        opcode,
+        writes: vec![],
+        state: vec![],
+        state_len: 0,
+        stdin: vec![],
+        clocks: 0,
+      };
+      self.pending = Some((record, *mc.mem(), mc.save()));
+    }
+    self.stdin_front = stdin.iter().take(4).copied().collect();
+    self.stdin_len = stdin.len();
+  }
+
+  pub fn check(
+    &mut self,
+    mc: &impl Tickable,
+    stdin: &VecDeque<u8>,
+    clocks: u128,
+  ) -> Option<String> {
+    // returns the reason execution should break, if any
+
+    if let Some((record, _, _)) = &mut self.pending {
+      let consumed = self.stdin_len.saturating_sub(stdin.len());
+      record.stdin.extend(self.stdin_front.iter().take(consumed));
+      record.clocks += clocks;
+      record
+        .writes
+        .extend(mc.accesses().iter().filter_map(|access| match access {
+          Access::Write(address) => Some(*address),
+          Access::Read(_) => None,
+        }));
+    }
+
+    if mc.boundary() {
+      if let Some((mut record, mem, before)) = self.pending.take() {
+        // note down every address written to and the machine state to rewind to
+        let written: BTreeSet<u8> = (0..MEM_SIZE)
+          .filter(|address| mem[*address] != mc.mem()[*address])
+          .map(|address| address as u8)
+          .chain(record.writes.iter().copied())
+          .collect();
+        record.writes = written.into_iter().collect();
+        let after = mc.save();
+        record.state = (before.iter().enumerate())
+          .filter(|(index, byte)| after.get(*index) != Some(*byte))
+          .map(|(index, byte)| (index, *byte))
+          .collect();
+        record.state_len = before.len();
+        self.history.push_back(record);
+        if self.history.len() > HISTORY_LEN {
+          self.history.pop_front();
+        }
+      }
+    }
+
+    for access in mc.accesses() {
+      match access {
+        Access::Read(address) if self.read_watchpoints.contains(address) => {
+          self.watch_hit = Some(format!("Read watchpoint at {:02X}", address))
+        }
+        Access::Write(address) if self.write_watchpoints.contains(address) => {
+          self.watch_hit = Some(format!("Write watchpoint at {:02X}", address))
+        }
+        _ => (),
+      }
+    }
+
+    if !mc.boundary() {
+      return None;
+    }
+
+    let (ip, sp, cf) = (mc.ip(), mc.sp(), mc.cf());
+    let sti = instruction_to_opcode(Ok(Instruction::Sti));
+    let mut reasons = vec![];
+
+    reasons.extend(self.watch_hit.take());
+    if self.breakpoints.contains(&ip) {
+      reasons.push(format!("Breakpoint at {:02X}", ip));
+    }
+    if let Some((comparison, operand, last)) = self.sp_condition {
+      let outcome = Debugger::compare(comparison, sp, operand);
+      self.sp_condition = Some((comparison, operand, outcome));
+      if outcome && !last {
+        reasons.push(format!("Condition SP{}{:02X}", comparison, operand));
+      }
+    }
+    if let Some((operand, last)) = self.cf_condition {
+      let outcome = cf == operand;
+      self.cf_condition = Some((operand, outcome));
+      if outcome && !last {
+        reasons.push(format!("Condition CF={:01X}", operand as u8));
+      }
+    }
+    reasons.extend(match self.until {
+      Some(Until::Boundary) => Some("Stepped".to_string()),
+      Some(Until::Return(until_ip, until_sp)) if ip == until_ip && sp > until_sp => {
+        Some("Stepped over call".to_string())
+      }
+      Some(Until::Unwind(until_sp)) if self.opcode == Some(sti) && sp > until_sp => {
+        Some(format!("Returned to {:02X}", ip))
+      }
+      Some(Until::Address(until_ip)) if ip == until_ip => Some(format!("Reached {:02X}", ip)),
+      _ => None,
+    });
+
+    if reasons.is_empty() {
+      return None;
+    }
+    self.until = None;
+    Some(reasons.join(", "))
+  }
+}
+
+impl std::fmt::Display for Debugger {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn render_addresses(addresses: &BTreeSet<u8>) -> String {
+      // collapse contiguous addresses into ranges
+      let mut ranges: Vec<(usize, usize)> = vec![];
+      for &address in addresses {
+        match ranges.last_mut() {
+          Some((_, end)) if *end == address as usize => *end += 1,
+          _ => ranges.push((address as usize, address as usize + 1)),
+        }
+      }
+      ranges
+        .iter()
+        .map(|&(start, end)| match end - start {
+          1 => format!("{:02X}", start),
+          _ => format!("{:02X}..{:02X}", start, end),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+    }
+
+    let timeline = self
+      .history
+      .iter()
+      .skip(self.history.len().saturating_sub(6))
+      .map(|record| {
+        let instruction = opcode_to_instruction(record.opcode);
+        format!("{:02X} {}", record.ip, instruction_to_token(instruction))
+      })
+      .collect::<Vec<String>>()
+      .join("  ");
+
+    let conditions = [
+      self
+        .sp_condition
+        .map(|(comparison, operand, _)| format!("SP{}{:02X}", comparison, operand)),
+      self
+        .cf_condition
+        .map(|(operand, _)| format!("CF={:01X}", operand as u8)),
+    ];
+
+    write!(
+      f,
+      "HST  {}\r\nBRK  {}\r\nRWP  {}\r\nWWP  {}\r\nCND  {}\r\n> {}\r\n",
+      timeline,
+      render_addresses(&self.breakpoints),
+      render_addresses(&self.read_watchpoints),
+      render_addresses(&self.write_watchpoints),
+      conditions
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join(" "),
+      self.command,
+    )
+  }
+}
@@ -0,0 +1,410 @@
+use super::*;
+
+pub fn execute_gdb<MC: Tickable>(
+  name: &str,
+  mut mc: MC,
+  target: &str,
+  input: Vec<u8>,
+  snapshot: Option<Snapshot>,
+) -> Result<(), Error> {
+  // serve the GDB Remote Serial Protocol over stdio or a local TCP port
+
+  use std::io::Read;
+  use std::sync::mpsc;
+
+  let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write>) = match target {
+    "-" => (Box::new(std::io::stdin()), Box::new(std::io::stdout())),
+    port => {
+      let port = port
+        .parse::<u16>()
+        .map_err(|_| Error(format!("Invalid value '{}' for flag '--gdb'", port)))?;
+      let listener = std::net::TcpListener::bind(("127.0.0.1", port))
+        .map_err(|_| Error(format!("Unable to listen on port {}", port)))?;
+      eprintln!("{}: Listening on port {}", name, port);
+      let (stream, _) = listener
+        .accept()
+        .map_err(|_| Error("Unable to accept connection".to_string()))?;
+      let reader =
+        (stream.try_clone()).map_err(|_| Error("Unable to accept connection".to_string()))?;
+      (Box::new(reader), Box::new(stream))
+    }
+  };
+
+  let (tx, rx) = mpsc::channel::<GdbInput>();
+  std::thread::spawn(move || {
+    let mut bytes = std::io::BufReader::new(reader)
+      .bytes()
+      .map_while(Result::ok);
+    while let Some(byte) = bytes.next() {
+      let input = match byte {
+        0x03 => GdbInput::Interrupt,
+        b'$' => {
+          let data: Vec<u8> = bytes.by_ref().take_while(|byte| *byte != b'#').collect();
+          let checksum: String = bytes.by_ref().take(2).map(|byte| byte as char).collect();
+          let valid = u8::from_str_radix(&checksum, 16)
+            .is_ok_and(|checksum| checksum == data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)));
+          match valid {
+            true => GdbInput::Packet(String::from_utf8_lossy(&data).to_string()),
+            false => GdbInput::Corrupt,
+          }
+        }
+        _ => continue, // acknowledgments
+      };
+      if tx.send(input).is_err() {
+        break;
+      }
+    }
+  });
+
+  let mut gdb = Gdb {
+    writer,
+    disconnected: false,
+    ack: true,
+    breakpoints: BTreeSet::new(),
+    watchpoints: vec![],
+    pending: VecDeque::new(),
+  };
+
+  let mut stdin = VecDeque::new();
+  let mut stdout = VecDeque::new();
+  let mut display = [0x00; DISPLAY_BUFFER_LEN];
+
+  mc.reset(&mut stdin, &mut stdout, &mut display, &mut 0x00);
+  if let Some(snapshot) = snapshot {
+    snapshot.apply(&mut mc, &mut stdin, &mut stdout, &mut display);
+  }
+  stdin.extend(input);
+
+  while let Some(input) = gdb.pending.pop_front().or_else(|| rx.recv().ok()) {
+    if gdb.disconnected {
+      break;
+    }
+    let packet = match input {
+      GdbInput::Packet(packet) => packet,
+      GdbInput::Interrupt => continue, // not running
+      GdbInput::Corrupt => {
+        gdb.send_raw("-");
+        continue;
+      }
+    };
+    if gdb.ack {
+      gdb.send_raw("+");
+    }
+
+    let (command, args) = packet.split_at(packet.len().min(1));
+    let response = match command {
+      "?" => "S05".to_string(),
+      "g" => format!("{:02X}{:02X}{:02X}", mc.ip(), mc.sp(), mc.cf() as u8),
+      "G" => match Gdb::parse_bytes(args).as_deref() {
+        Some(&[ip, sp, cf]) => {
+          mc.set_ip(ip);
+          mc.set_sp(sp);
+          mc.set_cf(cf != 0x00);
+          "OK".to_string()
+        }
+        _ => "E01".to_string(),
+      },
+      "p" => match u8::from_str_radix(args, 16) {
+        Ok(0x00) => format!("{:02X}", mc.ip()),
+        Ok(0x01) => format!("{:02X}", mc.sp()),
+        Ok(0x02) => format!("{:02X}", mc.cf() as u8),
+        _ => "E01".to_string(),
+      },
+      "P" => {
+        let register = args.split_once('=').and_then(|(register, value)| {
+          Some((
+            u8::from_str_radix(register, 16).ok()?,
+            *Gdb::parse_bytes(value)?.first()?,
+          ))
+        });
+        match register {
+          Some((0x00, value)) => mc.set_ip(value),
+          Some((0x01, value)) => mc.set_sp(value),
+          Some((0x02, value)) => mc.set_cf(value != 0x00),
+          _ => (),
+        }
+        match register {
+          Some((0x00..=0x02, _)) => "OK".to_string(),
+          _ => "E01".to_string(),
+        }
+      }
+      "m" => match Gdb::parse_range(args) {
+        Some((address, length)) => {
+          let io = Io {
+            stdin: &mut stdin,
+            stdout: &mut stdout,
+            display: &mut display,
+            controller: &mut 0x00,
+          };
+          // peripherals are peeked so that reading does not consume input
+          (address..address + length)
+            .map(|address| {
+              mc.bus()
+                .peek(address as u8, &io)
+                .unwrap_or(mc.mem()[address])
+            })
+            .map(|value| format!("{:02X}", value))
+            .collect()
+        }
+        None => "E01".to_string(),
+      },
+      "M" => {
+        let write = args.split_once(':').and_then(|(range, data)| {
+          let (address, length) = Gdb::parse_range(range)?;
+          let data = Gdb::parse_bytes(data)?;
+          (data.len() == length).then_some((address, data))
+        });
+        match write {
+          Some((address, data)) => {
+            let mut io = Io {
+              stdin: &mut stdin,
+              stdout: &mut stdout,
+              display: &mut display,
+              controller: &mut 0x00,
+            };
+            for (address, value) in (address..).zip(data) {
+              if !mc.bus_mut().write(address as u8, value, &mut io) {
+                mc.mem_mut()[address] = value;
+              }
+            }
+            "OK".to_string()
+          }
+          None => "E01".to_string(),
+        }
+      }
+      "Z" | "z" => {
+        let point = args.splitn(3, ',').collect::<Vec<&str>>();
+        let point = match point[..] {
+          [kind, address, length] => Gdb::parse_range(&format!("{},{}", address, length))
+            .and_then(|(address, length)| Some((kind.parse::<u8>().ok()?, address, length))),
+          _ => None,
+        };
+        match (command, point) {
+          ("Z", Some((0 | 1, address, _))) => {
+            gdb.breakpoints.insert(address as u8);
+            "OK".to_string()
+          }
+          ("z", Some((0 | 1, address, _))) => {
+            gdb.breakpoints.remove(&(address as u8));
+            "OK".to_string()
+          }
+          ("Z", Some((kind @ 2..=4, address, length))) => {
+            gdb.watchpoints.push((kind, address, length));
+            "OK".to_string()
+          }
+          ("z", Some((kind @ 2..=4, address, length))) => {
+            gdb
+              .watchpoints
+              .retain(|watchpoint| *watchpoint != (kind, address, length));
+            "OK".to_string()
+          }
+          _ => "".to_string(),
+        }
+      }
+      "s" | "c" => {
+        if let Ok(address) = u8::from_str_radix(args, 16) {
+          mc.set_ip(address);
+        }
+        gdb.resume(
+          &mut mc,
+          command == "s",
+          &rx,
+          &mut stdin,
+          &mut stdout,
+          &mut display,
+        )
+      }
+      "D" => {
+        gdb.send("OK");
+        break;
+      }
+      "k" => break,
+      "H" => "OK".to_string(),
+      _ if packet.starts_with("qSupported") => {
+        "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string()
+      }
+      _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+        let range = &packet["qXfer:features:read:target.xml:".len()..];
+        match range.split_once(',').and_then(|(offset, length)| {
+          Some((
+            usize::from_str_radix(offset, 16).ok()?,
+            usize::from_str_radix(length, 16).ok()?,
+          ))
+        }) {
+          Some((offset, length)) => {
+            let chunk: String = GDB_TARGET_XML.chars().skip(offset).take(length).collect();
+            match offset + chunk.len() < GDB_TARGET_XML.len() {
+              true => format!("m{}", chunk),
+              false => format!("l{}", chunk),
+            }
+          }
+          None => "E01".to_string(),
+        }
+      }
+      _ if packet == "QStartNoAckMode" => {
+        gdb.send("OK");
+        gdb.ack = false;
+        continue;
+      }
+      _ if packet == "qAttached" => "1".to_string(),
+      _ if packet == "qC" => "QC1".to_string(),
+      _ if packet == "qfThreadInfo" => "m1".to_string(),
+      _ if packet == "qsThreadInfo" => "l".to_string(),
+      _ => "".to_string(), // unsupported
+    };
+
+    gdb.send(&response);
+    if response.starts_with('W') || gdb.disconnected {
+      break;
+    }
+  }
+
+  let flushed = mc.bus_mut().flush();
+  flushed.and(match gdb.disconnected {
+    true => Err(Error("Connection to GDB lost".to_string())),
+    false => Ok(()),
+  })
+}
+
+const GDB_TARGET_XML: &str = concat!(
+  r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+  r#"<target version="1.0"><feature name="atto8.core">"#,
+  r#"<reg name="ip" bitsize="8" type="code_ptr" regnum="0"/>"#,
+  r#"<reg name="sp" bitsize="8" type="data_ptr"/>"#,
+  r#"<reg name="cf" bitsize="8" type="uint8"/>"#,
+  r#"</feature></target>"#,
+);
+
+enum GdbInput {
+  Packet(String),
+  Interrupt,
+  Corrupt,
+}
+
+struct Gdb {
+  writer: Box<dyn Write>,
+  disconnected: bool, // set once a write fails, ending the session
+  ack: bool,
+  breakpoints: BTreeSet<u8>,
+  watchpoints: Vec<(u8, usize, usize)>, // kind, address, length
+  pending: VecDeque<GdbInput>,          // received while running, handled once stopped
+}
+
+impl Gdb {
+  fn send_raw(&mut self, data: &str) {
+    if self.disconnected {
+      return;
+    }
+    let sent = (self.writer.write_all(data.as_bytes())).and_then(|()| self.writer.flush());
+    self.disconnected = sent.is_err();
+  }
+
+  fn send(&mut self, data: &str) {
+    let checksum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+    self.send_raw(&format!("${}#{:02x}", data, checksum));
+  }
+
+  fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+      .step_by(2)
+      .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+      .collect()
+  }
+
+  fn parse_range(range: &str) -> Option<(usize, usize)> {
+    // `address,length` within memory
+    let (address, length) = range.split_once(',')?;
+    let address = usize::from_str_radix(address, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+    (address + length <= MEM_SIZE).then_some((address, length))
+  }
+
+  fn resume(
+    &mut self,
+    mc: &mut impl Tickable,
+    single_step: bool,
+    rx: &std::sync::mpsc::Receiver<GdbInput>,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; DISPLAY_BUFFER_LEN],
+  ) -> String {
+    // run until a stop condition and return the stop reply
+
+    let mut watch_hit = None;
+    let mut instructions: u128 = 0;
+
+    let stop = loop {
+      // no controller is connected when debugging through GDB
+      match mc.tick(stdin, stdout, display, &mut 0x00) {
+        Ok(_) => (),
+        Err(TickTrap::Halted) => break "W00".to_string(),
+        Err(TickTrap::IllegalOpcode) => break "S04".to_string(),
+        Err(TickTrap::DebugRequest) => break "S05".to_string(),
+        Err(TickTrap::MicrocodeFault | TickTrap::BusContention) => break "S0A".to_string(),
+        Err(
+          TickTrap::StackOverflow
+          | TickTrap::CodeCollision
+          | TickTrap::UninitializedRead
+          | TickTrap::CodeModification
+          | TickTrap::DataExecution,
+        ) => break "S0B".to_string(),
+      }
+
+      for access in mc.accesses() {
+        let (address, read) = match access {
+          Access::Read(address) => (*address as usize, true),
+          Access::Write(address) => (*address as usize, false),
+        };
+        let hit = self.watchpoints.iter().find(|(kind, start, length)| {
+          (*start..*start + *length).contains(&address)
+            && match kind {
+              2 => !read,
+              3 => read,
+              _ => true,
+            }
+        });
+        if let Some((kind, _, _)) = hit {
+          let kind = ["watch", "rwatch", "awatch"][*kind as usize - 2];
+          watch_hit = watch_hit.or(Some(format!("T05{}:{:02x};", kind, address)));
+        }
+      }
+
+      if !mc.boundary() {
+        continue;
+      }
+      instructions += 1;
+
+      if let Some(watch_hit) = watch_hit {
+        break watch_hit;
+      }
+      if single_step || self.breakpoints.contains(&mc.ip()) {
+        break "S05".to_string();
+      }
+      // poll for interrupts every so often
+      if instructions & 0xFFF == 0 {
+        self.flush_console(stdout);
+        let mut interrupted = false;
+        for input in rx.try_iter() {
+          match input {
+            GdbInput::Interrupt => interrupted = true,
+            input => self.pending.push_back(input),
+          }
+        }
+        if interrupted || self.disconnected {
+          break "S02".to_string();
+        }
+      }
+    };
+
+    self.flush_console(stdout);
+    stop
+  }
+
+  fn flush_console(&mut self, stdout: &mut VecDeque<u8>) {
+    // forward standard output to the GDB console
+    if !stdout.is_empty() {
+      let output: String = stdout.drain(..).map(|c| format!("{:02x}", c)).collect();
+      self.send(&format!("O{}", output));
+    }
+  }
+}
@@ -0,0 +1,151 @@
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyAction {
+  Controller(u8), // index of the controller bit, primary D-pad first
+  ClearStdout,
+  ToggleState, // single steps in debug mode
+  ToggleDebug,
+  SaveSnapshot,
+  SwitchMachine, // move keyboard input to the next linked machine
+  Stdin,         // pass the key through to `stdin`
+}
+
+const CONTROLLER_ACTIONS: [&str; 8] = [
+  "primary_up",
+  "primary_down",
+  "primary_left",
+  "primary_right",
+  "secondary_up",
+  "secondary_down",
+  "secondary_left",
+  "secondary_right",
+];
+
+const KEY_NAMES: [(&str, console::Key); 16] = [
+  ("up", console::Key::ArrowUp),
+  ("down", console::Key::ArrowDown),
+  ("left", console::Key::ArrowLeft),
+  ("right", console::Key::ArrowRight),
+  ("pageup", console::Key::PageUp),
+  ("pagedown", console::Key::PageDown),
+  ("home", console::Key::Home),
+  ("end", console::Key::End),
+  ("enter", console::Key::Enter),
+  ("escape", console::Key::Escape),
+  ("backspace", console::Key::Backspace),
+  ("tab", console::Key::Tab),
+  ("backtab", console::Key::BackTab),
+  ("del", console::Key::Del),
+  ("insert", console::Key::Insert),
+  ("space", console::Key::Char(' ')),
+];
+
+pub struct KeyMap {
+  bindings: Vec<(console::Key, Vec<KeyAction>)>,
+}
+
+impl KeyMap {
+  pub fn actions(&self, key: &console::Key) -> Vec<KeyAction> {
+    // unbound keys pass through to `stdin`
+    match self.bindings.iter().find(|(bound, _)| bound == key) {
+      Some((_, actions)) => actions.clone(),
+      None => vec![KeyAction::Stdin],
+    }
+  }
+
+  pub fn bind(&mut self, key: console::Key, actions: Vec<KeyAction>) {
+    self.bindings.retain(|(bound, _)| *bound != key);
+    self.bindings.push((key, actions));
+  }
+}
+
+impl Default for KeyMap {
+  fn default() -> KeyMap {
+    // arrows for the primary D-pad and WASD for the secondary D-pad, for two-player games.
+    // WASD still reach `stdin` so that text typed into programs comes through intact
+    let controller = [
+      console::Key::ArrowUp,
+      console::Key::ArrowDown,
+      console::Key::ArrowLeft,
+      console::Key::ArrowRight,
+    ];
+    let mut bindings: Vec<(console::Key, Vec<KeyAction>)> = controller
+      .into_iter()
+      .enumerate()
+      .map(|(index, key)| (key, vec![KeyAction::Controller(index as u8)]))
+      .collect();
+    let wasd = ['w', 's', 'a', 'd'];
+    bindings.extend((wasd.into_iter().enumerate()).map(|(index, key)| {
+      let actions = vec![KeyAction::Controller(0x04 + index as u8), KeyAction::Stdin];
+      (console::Key::Char(key), actions)
+    }));
+    // the secondary D-pad keeps its former bindings for keyboards that have them
+    let secondary = [
+      console::Key::PageUp,
+      console::Key::PageDown,
+      console::Key::Home,
+      console::Key::End,
+    ];
+    bindings.extend(
+      (secondary.into_iter().enumerate())
+        .map(|(index, key)| (key, vec![KeyAction::Controller(0x04 + index as u8)])),
+    );
+    bindings.extend([
+      (console::Key::Del, vec![KeyAction::ClearStdout]),
+      (console::Key::Tab, vec![KeyAction::ToggleState]),
+      (console::Key::Escape, vec![KeyAction::ToggleDebug]),
+      (console::Key::Insert, vec![KeyAction::SaveSnapshot]),
+      (console::Key::BackTab, vec![KeyAction::SwitchMachine]),
+    ]);
+    KeyMap { bindings }
+  }
+}
+
+pub fn parse_key_map(source: &str) -> Result<KeyMap, Error> {
+  // one `<key> <action>...` per line, overriding the default bindings of `<key>`
+  let mut key_map = KeyMap::default();
+
+  for line in source.lines() {
+    let line = line.split('#').next().unwrap().trim();
+    if line.is_empty() {
+      continue;
+    }
+
+    let invalid = || Error(format!("Invalid key map record '{}'", line));
+    let mut words = line.split_whitespace();
+    let key = match words.next().unwrap() {
+      name if name.chars().count() == 1 => console::Key::Char(name.chars().next().unwrap()),
+      name => (KEY_NAMES.iter())
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| key.clone())
+        .ok_or_else(invalid)?,
+    };
+    let actions = words
+      .map(|word| match word {
+        "clear_stdout" => Ok(KeyAction::ClearStdout),
+        "toggle_state" => Ok(KeyAction::ToggleState),
+        "toggle_debug" => Ok(KeyAction::ToggleDebug),
+        "save_snapshot" => Ok(KeyAction::SaveSnapshot),
+        "switch_machine" => Ok(KeyAction::SwitchMachine),
+        "stdin" => Ok(KeyAction::Stdin),
+        word => (CONTROLLER_ACTIONS.iter())
+          .position(|action| *action == word)
+          .map(|index| KeyAction::Controller(index as u8))
+          .ok_or_else(invalid),
+      })
+      .collect::<Result<Vec<KeyAction>, Error>>()?;
+    key_map.bind(key, actions);
+  }
+
+  Ok(key_map)
+}
+
+pub fn load_key_map(flags: &BTreeMap<String, String>) -> Result<KeyMap, Error> {
+  match flags.get("key-map") {
+    None => Ok(KeyMap::default()),
+    Some(file) => std::fs::read_to_string(file)
+      .map_err(|_| Error(format!("Unable to read file '{}'", file)))
+      .and_then(|source| parse_key_map(&source)),
+  }
+}
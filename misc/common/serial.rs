@@ -0,0 +1,141 @@
+use super::*;
+
+pub struct Serial {
+  outputs: Vec<std::sync::mpsc::Sender<u8>>, // one per machine at the other end of the link
+  input: std::sync::mpsc::Receiver<u8>,
+  socket: Option<PathBuf>, // socket file to remove on shutdown, if this end created it
+  sent: Option<std::sync::mpsc::Receiver<()>>, // disconnects once output is done being sent
+}
+
+impl Serial {
+  pub fn mesh(count: usize) -> Vec<Serial> {
+    // links machines within one process. every machine receives what every other machine sends
+    let (senders, receivers): (Vec<_>, Vec<_>) =
+      (0..count).map(|_| std::sync::mpsc::channel()).unzip();
+    (receivers.into_iter().enumerate())
+      .map(|(index, input)| Serial {
+        outputs: (senders.iter().enumerate())
+          .filter(|(other, _)| *other != index)
+          .map(|(_, sender)| sender.clone())
+          .collect(),
+        input,
+        socket: None,
+        sent: None,
+      })
+      .collect()
+  }
+
+  #[cfg(unix)]
+  pub fn connect(file: &str) -> Result<Serial, Error> {
+    // the first machine to use a socket file listens on it and the second one connects to it
+    use std::io::Read;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let stream = UnixStream::connect(file);
+    let listener = match stream {
+      Ok(_) => None,
+      Err(_) => {
+        // a socket file nobody listens on was left behind by a machine that was killed
+        if std::fs::metadata(file).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+          std::fs::remove_file(file).ok();
+        }
+        Some(
+          UnixListener::bind(file)
+            .map_err(|_| Error(format!("Unable to listen on socket '{}'", file)))?,
+        )
+      }
+    };
+    let socket = listener.is_some().then(|| PathBuf::from(file));
+
+    // output sent before the other end connects is queued up
+    let (output, outgoing) = std::sync::mpsc::channel::<u8>();
+    let (incoming, input) = std::sync::mpsc::channel::<u8>();
+    let (done, sent) = std::sync::mpsc::channel::<()>();
+    std::thread::spawn(move || {
+      let _done = done;
+      let stream = match listener {
+        Some(listener) => listener.accept().map(|(stream, _)| stream),
+        None => stream,
+      };
+      let Ok(mut stream) = stream else { return };
+      if let Ok(mut reader) = stream.try_clone() {
+        std::thread::spawn(move || {
+          let mut buffer = [0x00; 0x100];
+          while let Ok(len @ 1..) = reader.read(&mut buffer) {
+            if buffer[..len]
+              .iter()
+              .any(|byte| incoming.send(*byte).is_err())
+            {
+              break;
+            }
+          }
+        });
+      }
+      for byte in outgoing {
+        if stream.write_all(&[byte]).is_err() {
+          break;
+        }
+      }
+    });
+
+    Ok(Serial {
+      outputs: vec![output],
+      input,
+      socket,
+      sent: Some(sent),
+    })
+  }
+
+  #[cfg(not(unix))]
+  pub fn connect(file: &str) -> Result<Serial, Error> {
+    Err(Error(format!(
+      "Unable to listen on socket '{}', sockets are only supported on Unix",
+      file
+    )))
+  }
+}
+
+impl Peripheral for Serial {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    // attached in front of `Stdio` so writes reach it first
+    STDIO_BUFFER as u8..=STDIO_BUFFER as u8
+  }
+
+  fn peek(&self, _address: u8, _io: &Io) -> Option<u8> {
+    None // reads are served by `Stdio`
+  }
+
+  fn write(&mut self, _address: u8, value: u8, io: &mut Io) -> bool {
+    // `stdout` is sent down the link and still shown locally
+    for output in &self.outputs {
+      output.send(value).ok();
+    }
+    io.stdout.push_back(value);
+    true
+  }
+
+  fn tick(&mut self, _clocks: u128, io: &mut Io) {
+    // bytes received from the link are appended to `stdin`
+    io.stdin.extend(self.input.try_iter());
+  }
+
+  fn flush(&mut self) -> Result<(), Error> {
+    // give queued output a moment to reach the other end, which may never connect
+    self.outputs.clear();
+    if let Some(sent) = self.sent.take() {
+      sent.recv_timeout(std::time::Duration::from_secs(1)).ok();
+    }
+    if let Some(socket) = self.socket.take() {
+      std::fs::remove_file(socket).ok();
+    }
+    Ok(())
+  }
+}
+
+pub fn create_link(flags: &BTreeMap<String, String>) -> Result<Option<Serial>, Error> {
+  flags
+    .get("link")
+    .map(|file| Serial::connect(file))
+    .transpose()
+}
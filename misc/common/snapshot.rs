@@ -0,0 +1,130 @@
+use super::*;
+
+pub struct Snapshot {
+  pub state: Vec<u8>,
+  pub stdin: VecDeque<u8>,
+  pub stdout: VecDeque<u8>,
+  pub display: [u8; DISPLAY_BUFFER_LEN],
+  pub controller: u8,
+  pub clocks: u128,
+}
+
+const SNAPSHOT_MAGIC: &[u8] = b"ATTO8SNP";
+const SNAPSHOT_VERSION: u8 = 0x04;
+
+impl Snapshot {
+  pub fn take(
+    mc: &impl Tickable,
+    stdin: &VecDeque<u8>,
+    stdout: &VecDeque<u8>,
+    display: &[u8; DISPLAY_BUFFER_LEN],
+    controller: u8,
+    clocks: u128,
+  ) -> Snapshot {
+    Snapshot {
+      state: mc.save(),
+      stdin: stdin.clone(),
+      stdout: stdout.clone(),
+      display: *display,
+      controller,
+      clocks,
+    }
+  }
+
+  pub fn apply(
+    self,
+    mc: &mut impl Tickable,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; DISPLAY_BUFFER_LEN],
+  ) -> u128 {
+    // returns the clock count the snapshot was taken at
+    mc.restore(&self.state);
+    *stdin = self.stdin;
+    *stdout = self.stdout;
+    *display = self.display;
+    self.clocks
+  }
+
+  pub fn save(&self, file: &str) -> Result<(), Error> {
+    // magic, version, then every field with variable-length fields prefixed by their length
+
+    let mut bytes = vec![];
+    bytes.extend(SNAPSHOT_MAGIC);
+    bytes.push(SNAPSHOT_VERSION);
+    bytes.extend(self.clocks.to_le_bytes());
+    bytes.push(self.controller);
+    bytes.extend(self.display);
+    for field in [
+      &Vec::from(self.stdin.clone()),
+      &Vec::from(self.stdout.clone()),
+      &self.state,
+    ] {
+      bytes.extend((field.len() as u32).to_le_bytes());
+      bytes.extend(field);
+    }
+
+    std::fs::write(file, bytes).map_err(|_| Error(format!("Unable to write file '{}'", file)))
+  }
+
+  pub fn load(file: &str, mc: &impl Tickable) -> Result<Snapshot, Error> {
+    // `mc` is only used to validate the size of the machine state
+
+    let bytes =
+      std::fs::read(file).map_err(|_| Error(format!("Unable to read file '{}'", file)))?;
+    let error = || Error(format!("Snapshot '{}' is malformed", file));
+
+    let mut rest = &bytes[..];
+    let mut take = |len: usize| -> Result<Vec<u8>, Error> {
+      let (taken, remaining) = rest.split_at_checked(len).ok_or_else(error)?;
+      rest = remaining;
+      Ok(taken.to_vec())
+    };
+
+    macro_rules! take_field {
+      () => {{
+        let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        take(len)?
+      }};
+    }
+
+    if take(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+      Err(error())?;
+    }
+    let version = take(1)?[0];
+    if version != SNAPSHOT_VERSION {
+      Err(Error(format!(
+        "Snapshot '{}' has unsupported version {:02X}",
+        file, version
+      )))?;
+    }
+
+    let snapshot = Snapshot {
+      clocks: u128::from_le_bytes(take(16)?.try_into().unwrap()),
+      controller: take(1)?[0],
+      display: take(DISPLAY_BUFFER_LEN)?.try_into().unwrap(),
+      stdin: take_field!().into(),
+      stdout: take_field!().into(),
+      state: take_field!(),
+    };
+
+    if snapshot.state.len() != mc.save().len() {
+      Err(Error(format!(
+        "Snapshot '{}' was taken on a different machine",
+        file
+      )))?;
+    }
+
+    Ok(snapshot)
+  }
+}
+
+pub fn load_snapshot(
+  flags: &BTreeMap<String, String>,
+  mc: &impl Tickable,
+) -> Result<Option<Snapshot>, Error> {
+  flags
+    .get("restore")
+    .map(|file| Snapshot::load(file, mc))
+    .transpose()
+}
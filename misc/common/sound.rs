@@ -0,0 +1,161 @@
+use super::*;
+
+pub struct Sound {
+  output: std::io::BufWriter<std::fs::File>,
+  file: String,
+  failed: bool, // a write failed, reported once the output is flushed
+  wav: bool,
+  clock_speed: u128, // clocks per second for sample timing
+  clocks: u128,      // clocks elapsed since the output was created
+  samples: u64,      // samples written since the output was created
+  tone: u8,          // tone register, the half period of the square wave in units of 0x10 clocks
+  control: u8,       // control register, bit 0 gates the square wave
+  level: bool,
+  phase: u16, // clocks elapsed since the square wave last changed level
+}
+
+const SAMPLE_RATE: u32 = 44100;
+
+impl Sound {
+  pub fn new(file: &str, clock_speed: u128) -> Result<Sound, Error> {
+    let error = || Error(format!("Unable to write file '{}'", file));
+    let output = std::fs::File::create(file).map_err(|_| error())?;
+    let mut sound = Sound {
+      output: std::io::BufWriter::new(output),
+      file: file.to_string(),
+      failed: false,
+      wav: file.ends_with(".wav"),
+      clock_speed,
+      clocks: 0,
+      samples: 0,
+      tone: 0x00,
+      control: 0x00,
+      level: false,
+      phase: 0,
+    };
+    if sound.wav {
+      // sizes are filled in by `flush`. until then, the maximum size keeps the file playable
+      // should the process be killed
+      let header = sound.wav_header(u64::MAX);
+      sound.output.write_all(&header).map_err(|_| error())?;
+    }
+    Ok(sound)
+  }
+
+  fn wav_header(&self, samples: u64) -> Vec<u8> {
+    // http://soundfile.sapp.org/doc/WaveFormat/
+    // 8-bit unsigned mono PCM. sizes are capped at the largest a WAV file can describe
+    let samples = samples.min(u32::MAX as u64 - 36) as u32;
+    let mut header = b"RIFF".to_vec();
+    header.extend((36 + samples).to_le_bytes());
+    header.extend(b"WAVEfmt ");
+    header.extend(16u32.to_le_bytes());
+    header.extend(1u16.to_le_bytes()); // PCM
+    header.extend(1u16.to_le_bytes()); // mono
+    header.extend(SAMPLE_RATE.to_le_bytes());
+    header.extend(SAMPLE_RATE.to_le_bytes()); // bytes per second
+    header.extend(1u16.to_le_bytes()); // bytes per sample
+    header.extend(8u16.to_le_bytes()); // bits per sample
+    header.extend(b"data");
+    header.extend(samples.to_le_bytes());
+    header
+  }
+
+  fn sample(&self) -> u8 {
+    match (self.control & 0x01 != 0x00, self.level) {
+      (false, _) => 0x80,
+      (true, false) => 0x40,
+      (true, true) => 0xC0,
+    }
+  }
+}
+
+impl Peripheral for Sound {
+  fn range(&self) -> std::ops::RangeInclusive<u8> {
+    SOUND_TONE as u8..=SOUND_CONTROL as u8
+  }
+
+  fn peek(&self, address: u8, _io: &Io) -> Option<u8> {
+    match address as usize {
+      SOUND_TONE => Some(self.tone),
+      _ => Some(self.control),
+    }
+  }
+
+  fn write(&mut self, address: u8, value: u8, _io: &mut Io) -> bool {
+    match address as usize {
+      SOUND_TONE => {
+        // restart the square wave so notes start in phase
+        self.tone = value;
+        self.level = false;
+        self.phase = 0;
+      }
+      _ => self.control = value,
+    }
+    true
+  }
+
+  fn tick(&mut self, clocks: u128, _io: &mut Io) {
+    for _ in 0..clocks {
+      // sample `n` is taken at clock `n * clock_speed / SAMPLE_RATE`
+      while self.samples as u128 * self.clock_speed <= self.clocks * SAMPLE_RATE as u128 {
+        self.failed |= self.output.write_all(&[self.sample()]).is_err();
+        self.samples += 1;
+      }
+      self.clocks += 1;
+      self.phase += 1;
+      if self.phase >= (self.tone as u16 + 1) * 0x10 {
+        self.phase = 0;
+        self.level = !self.level;
+      }
+    }
+  }
+
+  fn reset(&mut self, _mem: &[u8; MEM_SIZE], _io: &mut Io) {
+    // the output keeps running across resets
+    self.tone = 0x00;
+    self.control = 0x00;
+    self.level = false;
+    self.phase = 0;
+  }
+
+  fn save(&self) -> Vec<u8> {
+    let mut state = vec![self.tone, self.control, self.level as u8];
+    state.extend(self.phase.to_le_bytes());
+    state
+  }
+
+  fn restore(&mut self, state: &[u8]) {
+    self.tone = state[0];
+    self.control = state[1];
+    self.level = state[2] != 0x00;
+    self.phase = u16::from_le_bytes([state[3], state[4]]);
+  }
+
+  fn flush(&mut self) -> Result<(), Error> {
+    use std::io::{Seek, SeekFrom};
+    let error = || Error(format!("Unable to write file '{}'", self.file));
+    if self.failed {
+      Err(error())?;
+    }
+
+    let header = self.wav_header(self.samples);
+    let result = match self.wav {
+      true => (self.output.seek(SeekFrom::Start(0)))
+        .and_then(|_| self.output.write_all(&header))
+        .and_then(|()| self.output.seek(SeekFrom::End(0)))
+        .and_then(|_| self.output.flush()),
+      false => self.output.flush(),
+    };
+    result.map_err(|_| error())
+  }
+}
+
+pub fn create_sound(flags: &BTreeMap<String, String>) -> Result<Option<Sound>, Error> {
+  flags
+    .get("sound")
+    .map(|file| {
+      parse_nominal_clock_speed(flags).and_then(|clock_speed| Sound::new(file, clock_speed))
+    })
+    .transpose()
+}
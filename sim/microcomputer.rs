@@ -0,0 +1,617 @@
+use crate::common;
+use crate::common::*;
+use std::collections::VecDeque;
+
+pub struct Microcomputer {
+  mem: [u8; common::MEM_SIZE], // memory
+  mp: Microprocessor,          // microprocessor
+  bus: Bus,                    // memory-mapped peripherals
+  bus_clocks: u16,             // clocks of the current instruction, not yet seen by peripherals
+
+  clk: Clock,   // clock
+  rst: Reset,   // reset
+  addr: u8,     // address bus
+  data: u8,     // data bus
+  read: Signal, // memory read
+  wrt: Signal,  // memory write
+
+  fixed_point: Option<(u8, u8, bool)>, // IP, SP and CF after last `sti`
+  side_effect: bool,                   // peripheral state changed since last `sti`
+  dirty: Vec<(u8, u8)>,                // bytes written since last `sti` and their value back then
+  accesses: Vec<Access>,               // memory accesses during last tick
+}
+
+struct Microprocessor {
+  ip: u8,   // instruction pointer
+  sp: u8,   // stack pointer
+  cf: bool, // carry flag
+  il: u8,   // instruction latch
+  sc: u8,   // step counter
+  al: u8,   // address latch
+  xl: u8,   // X latch
+  yl: u8,   // Y latch
+  zl: u8,   // Z latch
+
+  ctrl: ControlWord, // control word derivation
+  pull: Signal,      // pull-up derivation
+
+  ones: u8,   // ones derivation
+  sum: u8,    // sum derivation
+  nand: u8,   // not-and derivation
+  cin: bool,  // sum carry-in derivation
+  cout: bool, // sum carry-out derivation
+  zero: bool, // nand is-zero derivation
+
+  mic: [u16; common::MIC_SIZE], // microcode derivation
+}
+
+pub enum Clock {
+  Rising,
+  High,
+  Falling,
+  Low,
+}
+
+pub enum Reset {
+  Asserted,
+  Deasserted,
+}
+
+impl Microcomputer {
+  pub fn new(
+    memory_image: [u8; common::MEM_SIZE],
+    microcode_image: [u16; common::MIC_SIZE],
+  ) -> Microcomputer {
+    Microcomputer {
+      mem: memory_image,
+      mp: Microprocessor {
+        ip: 0x00,
+        sp: 0x00,
+        cf: false,
+        il: 0x00,
+        sc: 0x00,
+        al: 0x00,
+        xl: 0x00,
+        yl: 0x00,
+        zl: 0x00,
+
+        ctrl: ControlWord::default(),
+        pull: Signal::Inactive,
+
+        ones: 0x00,
+        sum: 0x00,
+        nand: 0x00,
+        cin: false,
+        cout: false,
+        zero: false,
+
+        mic: microcode_image,
+      },
+      bus: Bus::default(),
+      bus_clocks: 0,
+
+      clk: Clock::Low,
+      rst: Reset::Deasserted,
+      addr: 0x00,
+      data: 0x00,
+      read: Signal::Inactive,
+      wrt: Signal::Inactive,
+
+      fixed_point: None,
+      side_effect: false,
+      dirty: vec![],
+      accesses: vec![],
+    }
+  }
+}
+
+impl Tickable for Microcomputer {
+  fn reset(
+    &mut self,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; common::DISPLAY_BUFFER_LEN],
+    controller: &mut u8,
+  ) {
+    self.rst = Reset::Asserted;
+    self.fixed_point = None;
+    self.dirty.clear();
+    self.bus_clocks = 0;
+    self
+      .tick(stdin, stdout, display, controller)
+      .unwrap_or_else(|_| panic!("Tick trap during reset sequence"));
+    self.rst = Reset::Deasserted;
+  }
+
+  fn tick(
+    &mut self,
+    stdin: &mut VecDeque<u8>,
+    stdout: &mut VecDeque<u8>,
+    display: &mut [u8; common::DISPLAY_BUFFER_LEN],
+    controller: &mut u8,
+  ) -> Result<u128, TickTrap> {
+    self.accesses.clear();
+    let mp = &mut self.mp;
+    let mut io = Io {
+      stdin,
+      stdout,
+      display,
+      controller,
+    };
+
+    // clock
+    match self.clk {
+      Clock::Rising => self.clk = Clock::High,
+      Clock::High => self.clk = Clock::Falling,
+      Clock::Falling => self.clk = Clock::Low,
+      Clock::Low => self.clk = Clock::Rising,
+    };
+    if let Reset::Asserted = self.rst {
+      self.clk = Clock::Low;
+    }
+
+    // step counter
+    if let Clock::Falling = self.clk {
+      if true {
+        mp.sc = mp.sc.wrapping_add(1) & (0x20 - 1);
+      }
+    }
+
+    // microcode and control logic
+    let il = match mp.il & 0x80 {
+      0b0 => mp.il | 0xF0, // map `psh` to `phn` as both have equivalent microcode
+      _ => mp.il,          // not `psh`; pass through
+    };
+    let il = il & (0x80 - 1); // ignore `psh`s as they have been mapped to `phn`s
+    mp.ctrl = common::u16_into_result(
+      mp.mic[(il as usize * 0x02 * 0x20) | (mp.cf as usize * 0x20) | mp.sc as usize],
+    )?;
+    let active_count = [
+      mp.ctrl.mem_data,
+      mp.ctrl.ip_data,
+      mp.ctrl.sp_data,
+      mp.ctrl.sum_data,
+      mp.ctrl.nand_data,
+    ]
+    .into_iter()
+    .filter(|s| match s {
+      Signal::Active => true,
+      Signal::Inactive => false,
+    })
+    .count();
+    mp.pull = match active_count {
+      0 => Ok(Signal::Active),
+      1 => Ok(Signal::Inactive),
+      _ => Err(TickTrap::BusContention),
+    }?;
+
+    // ones
+    mp.ones = 0xFF;
+    if let Signal::Active = mp.pull {
+      self.data = mp.ones;
+    }
+
+    // instruction latch and step counter
+    if let Clock::Rising = self.clk {
+      if let Signal::Active = mp.ctrl.data_il {
+        mp.il = self.data;
+      }
+    }
+    if let Signal::Active = mp.ctrl.clr_sc {
+      mp.sc = 0x00; // asynchronous
+    }
+    if let Reset::Asserted = self.rst {
+      mp.il = 0x00;
+      mp.sc = 0x00;
+    }
+
+    // instruction pointer
+    if let Clock::Rising = self.clk {
+      if let Signal::Active = mp.ctrl.data_ip {
+        mp.ip = self.data;
+      }
+    }
+    if let Signal::Active = mp.ctrl.ip_data {
+      self.data = mp.ip;
+    }
+    if let Reset::Asserted = self.rst {
+      mp.ip = 0x00;
+    }
+
+    // stack pointer
+    if let Clock::Rising = self.clk {
+      if let Signal::Active = mp.ctrl.data_sp {
+        mp.sp = self.data;
+      }
+    }
+    if let Signal::Active = mp.ctrl.sp_data {
+      self.data = mp.sp;
+    }
+    if let Reset::Asserted = self.rst {
+      mp.sp = 0x00;
+    }
+
+    // carry flag
+    if let Clock::Rising = self.clk {
+      if let Signal::Active = mp.ctrl.data_cf {
+        mp.cf = match (mp.ctrl.sum_data, mp.ctrl.nand_data) {
+          (Signal::Active, Signal::Inactive) => Ok(mp.cout),
+          (Signal::Inactive, Signal::Active) => Ok(mp.zero),
+          _ => Err(TickTrap::BusContention),
+        }?;
+      }
+    }
+    if let Reset::Asserted = self.rst {
+      mp.cf = false;
+    }
+
+    // address latch and memory
+    self.addr = mp.al;
+    self.read = mp.ctrl.mem_data;
+    self.wrt = mp.ctrl.data_mem;
+    if let Clock::Rising = self.clk {
+      if let Signal::Active = mp.ctrl.data_al {
+        mp.al = self.data;
+      }
+      if let Signal::Active = self.wrt {
+        self.accesses.push(Access::Write(self.addr));
+        // peripherals
+        let previous = self.bus.peek(self.addr, &io);
+        if self.bus.write(self.addr, self.data, &mut io) {
+          self.side_effect |= self.bus.has_side_effects(self.addr) || previous != Some(self.data);
+        } else {
+          if !self.dirty.iter().any(|(dirty, _)| *dirty == self.addr) {
+            self.dirty.push((self.addr, self.mem[self.addr as usize]));
+          }
+          self.mem[self.addr as usize] = self.data;
+        }
+      }
+    }
+    if let Signal::Active = self.read {
+      if let Clock::Rising = self.clk {
+        self.accesses.push(Access::Read(self.addr));
+      }
+      // peripherals. side effects of reads take place on the rising edge only
+      let value = match self.clk {
+        Clock::Rising => self.bus.read(self.addr, &mut io),
+        _ => self.bus.peek(self.addr, &io),
+      };
+      self.data = match value {
+        Some(value) => {
+          if let Clock::Rising = self.clk {
+            self.side_effect |= self.bus.has_side_effects(self.addr);
+          }
+          value
+        }
+        None => self.mem[self.addr as usize],
+      };
+    }
+    if let Reset::Asserted = self.rst {
+      mp.al = 0x00;
+      self.bus.reset(&self.mem, &mut io);
+    }
+
+    // X latch and Y latch and Z latch
+    let sum = mp.xl as u16 + mp.yl as u16 + mp.cin as u16;
+    let nand = !(mp.yl & mp.zl);
+    mp.sum = sum as u8;
+    mp.cout = sum > 0xFF;
+    mp.nand = nand;
+    mp.zero = nand == 0x00;
+    mp.cin = match mp.ctrl.set_cin {
+      Signal::Active => true,
+      Signal::Inactive => false,
+    };
+    if let Clock::Rising = self.clk {
+      if let Signal::Active = mp.ctrl.data_xl {
+        mp.xl = self.data;
+      }
+      if let Signal::Active = mp.ctrl.data_yl {
+        mp.yl = self.data;
+      }
+      if let Signal::Active = mp.ctrl.data_zl {
+        mp.zl = self.data;
+      }
+    }
+    if let Signal::Active = mp.ctrl.sum_data {
+      self.data = mp.sum;
+    }
+    if let Signal::Active = mp.ctrl.nand_data {
+      self.data = mp.nand;
+    }
+    if let Reset::Asserted = self.rst {
+      mp.xl = 0x00;
+      mp.yl = 0x00;
+      mp.zl = 0x00;
+    }
+
+    // jumping back to an identical state without side effects means looping forever. bytes
+    // written over and then restored, as by the pushes of a jump, leave the state identical
+    let sti = common::instruction_to_opcode(Ok(Instruction::Sti));
+    if self.boundary() && self.mp.il == sti {
+      let state = (self.mp.ip, self.mp.sp, self.mp.cf);
+      let changed =
+        (self.dirty.iter()).any(|(address, value)| self.mem[*address as usize] != *value);
+      if !self.side_effect && !changed && self.fixed_point == Some(state) {
+        return Err(TickTrap::Halted);
+      }
+      self.fixed_point = Some(state);
+      self.side_effect = false;
+      self.dirty.clear();
+    }
+
+    let clocks = match self.clk {
+      Clock::Rising => 1,
+      _ => 0,
+    };
+    // peripherals advance one instruction at a time, exactly as with `emu`
+    self.bus_clocks += clocks as u16;
+    if self.boundary() {
+      self.bus.tick(self.bus_clocks as u128, &mut io);
+      self.bus_clocks = 0;
+    }
+    Ok(clocks)
+  }
+
+  fn boundary(&self) -> bool {
+    // the step counter is cleared on the falling edge that ends an instruction, but also after
+    // every shift of `rot` save for the last, which leaves the carry flag set to continue shifting
+    let rot = matches!(
+      common::opcode_to_instruction(self.mp.il),
+      Ok(Instruction::Rot(_))
+    );
+    matches!(self.clk, Clock::Falling) && self.mp.sc == 0x00 && !(rot && self.mp.cf)
+  }
+
+  fn accesses(&self) -> &[Access] {
+    &self.accesses
+  }
+
+  fn ip(&self) -> u8 {
+    self.mp.ip
+  }
+
+  fn sp(&self) -> u8 {
+    self.mp.sp
+  }
+
+  fn cf(&self) -> bool {
+    self.mp.cf
+  }
+
+  fn mem(&self) -> &[u8; common::MEM_SIZE] {
+    &self.mem
+  }
+
+  fn set_ip(&mut self, ip: u8) {
+    self.mp.ip = ip;
+  }
+
+  fn set_sp(&mut self, sp: u8) {
+    self.mp.sp = sp;
+  }
+
+  fn set_cf(&mut self, cf: bool) {
+    self.mp.cf = cf;
+  }
+
+  fn mem_mut(&mut self) -> &mut [u8; common::MEM_SIZE] {
+    &mut self.mem
+  }
+
+  fn trace(&self) -> Option<String> {
+    // one record per microstep, taken once the rising edge has been latched
+    let Clock::Rising = self.clk else {
+      return None;
+    };
+
+    let mp = &self.mp;
+    let ctrl = [
+      ("data_ip", mp.ctrl.data_ip),
+      ("data_sp", mp.ctrl.data_sp),
+      ("data_cf", mp.ctrl.data_cf),
+      ("data_il", mp.ctrl.data_il),
+      ("data_al", mp.ctrl.data_al),
+      ("data_xl", mp.ctrl.data_xl),
+      ("data_yl", mp.ctrl.data_yl),
+      ("data_zl", mp.ctrl.data_zl),
+      ("mem_data", mp.ctrl.mem_data),
+      ("data_mem", mp.ctrl.data_mem),
+      ("clr_sc", mp.ctrl.clr_sc),
+      ("set_cin", mp.ctrl.set_cin),
+      ("ip_data", mp.ctrl.ip_data),
+      ("sp_data", mp.ctrl.sp_data),
+      ("sum_data", mp.ctrl.sum_data),
+      ("nand_data", mp.ctrl.nand_data),
+    ]
+    .into_iter()
+    .filter(|(_, signal)| matches!(signal, Signal::Active))
+    .map(|(name, _)| name)
+    .collect::<Vec<&str>>();
+
+    Some(format!(
+      "{:02X} {:02X} {:01X} {:02X} {:02X} {:02X} {:02X} {:02X} {:02X} {:02X} {}",
+      mp.ip,
+      mp.sp,
+      mp.cf as u8,
+      mp.il,
+      mp.sc,
+      mp.al,
+      mp.xl,
+      mp.yl,
+      mp.zl,
+      self.data,
+      match ctrl[..] {
+        [] => "-".to_string(),
+        _ => ctrl.join(","),
+      }
+    ))
+  }
+
+  fn save(&self) -> Vec<u8> {
+    let signal = |signal: &Signal| matches!(signal, Signal::Active) as u8;
+    let mp = &self.mp;
+    let fixed_point = self.fixed_point.unwrap_or_default();
+    let ctrl: u16 = mp.ctrl.into();
+    let mut state = self.mem.to_vec();
+    state.extend([
+      mp.ip,
+      mp.sp,
+      mp.cf as u8,
+      mp.il,
+      mp.sc,
+      mp.al,
+      mp.xl,
+      mp.yl,
+      mp.zl,
+    ]);
+    state.extend(ctrl.to_le_bytes());
+    state.extend([signal(&mp.pull), mp.ones, mp.sum, mp.nand]);
+    state.extend([mp.cin as u8, mp.cout as u8, mp.zero as u8]);
+    state.extend([
+      match self.clk {
+        Clock::Rising => 0x00,
+        Clock::High => 0x01,
+        Clock::Falling => 0x02,
+        Clock::Low => 0x03,
+      },
+      matches!(self.rst, Reset::Asserted) as u8,
+    ]);
+    state.extend([self.addr, self.data, signal(&self.read), signal(&self.wrt)]);
+    state.extend([
+      self.fixed_point.is_some() as u8,
+      fixed_point.0,
+      fixed_point.1,
+      fixed_point.2 as u8,
+    ]);
+    state.push(self.side_effect as u8);
+    state.extend(self.bus_clocks.to_le_bytes());
+    state.extend(common::save_dirty(&self.dirty));
+    state.extend(self.bus.save());
+    state
+  }
+
+  fn restore(&mut self, state: &[u8]) {
+    let signal = |byte: u8| match byte {
+      0x00 => Signal::Inactive,
+      _ => Signal::Active,
+    };
+    let (mem, state) = state.split_at(common::MEM_SIZE);
+    let (state, rest) = state.split_at(31);
+    let (dirty, bus) = rest.split_at(common::DIRTY_STATE_LEN);
+    self.dirty = common::restore_dirty(dirty);
+    self.mem.copy_from_slice(mem);
+    self.bus.restore(bus);
+    let [ip, sp, cf, il, sc, al, xl, yl, zl, ctrl_lo, ctrl_hi, pull, ones, sum, nand, cin, cout, zero, clk, rst, addr, data, read, wrt, fixed, fixed_ip, fixed_sp, fixed_cf, side_effect, bus_clocks_lo, bus_clocks_hi] =
+      state.try_into().unwrap();
+    let mp = &mut self.mp;
+    (mp.ip, mp.sp, mp.cf) = (ip, sp, cf != 0x00);
+    (mp.il, mp.sc, mp.al, mp.xl, mp.yl, mp.zl) = (il, sc, al, xl, yl, zl);
+    mp.ctrl = u16::from_le_bytes([ctrl_lo, ctrl_hi]).into();
+    mp.pull = signal(pull);
+    (mp.ones, mp.sum, mp.nand) = (ones, sum, nand);
+    (mp.cin, mp.cout, mp.zero) = (cin != 0x00, cout != 0x00, zero != 0x00);
+    self.clk = match clk {
+      0x00 => Clock::Rising,
+      0x01 => Clock::High,
+      0x02 => Clock::Falling,
+      _ => Clock::Low,
+    };
+    self.rst = match rst {
+      0x00 => Reset::Deasserted,
+      _ => Reset::Asserted,
+    };
+    (self.addr, self.data) = (addr, data);
+    (self.read, self.wrt) = (signal(read), signal(wrt));
+    self.fixed_point = (fixed != 0x00).then_some((fixed_ip, fixed_sp, fixed_cf != 0x00));
+    self.side_effect = side_effect != 0x00;
+    self.bus_clocks = u16::from_le_bytes([bus_clocks_lo, bus_clocks_hi]);
+  }
+
+  fn bus(&self) -> &Bus {
+    &self.bus
+  }
+
+  fn bus_mut(&mut self) -> &mut Bus {
+    &mut self.bus
+  }
+}
+
+impl std::fmt::Display for Microcomputer {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{}\r\n{}\r\n{}",
+      self.mp,
+      format_args!(
+        "CLK  RST  ADDR  DATA  READ  WRT\r\n{}  {}  {:02X}    {:02X}    {}    {}\r\n",
+        self.clk, self.rst, self.addr, self.data, self.read, self.wrt
+      ),
+      common::render_memory(&self.mem, self.mp.ip, self.mp.sp, self.mp.cf),
+    )
+  }
+}
+
+impl std::fmt::Display for Microprocessor {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{}\r\n{}\r\n{}\r\n{}",
+      format_args!(
+        "IP  SP  CF  IL  SC  AL  XL  YL  ZL\r\n{:02X}  {:02X}  {:01X}   {:02X}  {:02X}  {:02X}  {:02X}  {:02X}  {:02X}\r\n",
+        self.ip, self.sp, self.cf as u8, self.il, self.sc, self.al, self.xl, self.yl, self.zl
+      ),
+      format_args!(
+        "CTRL  {} {} {} {} {} {} {} {}\r\n      {} {} {} {} {} {} {} {}\r\n",
+        self.ctrl.data_ip,
+        self.ctrl.data_sp,
+        self.ctrl.data_cf,
+        self.ctrl.data_il,
+        self.ctrl.data_al,
+        self.ctrl.data_xl,
+        self.ctrl.data_yl,
+        self.ctrl.data_zl,
+        self.ctrl.mem_data,
+        self.ctrl.data_mem,
+        self.ctrl.clr_sc,
+        self.ctrl.set_cin,
+        self.ctrl.ip_data,
+        self.ctrl.sp_data,
+        self.ctrl.sum_data,
+        self.ctrl.nand_data,
+      ),
+      format_args!(
+        "PULL  ONES  SUM  NAND  CIN  COUT  ZERO\r\n{}    {:02X}    {:02X}   {:02X}    {:01X}    {:01X}     {:01X}\r\n",
+        self.pull, self.ones, self.sum, self.nand, self.cin as u8, self.cout as u8, self.zero as u8,
+      ),
+      format_args!(
+        "MIC  ({:#X} words)\r\n",
+        self.mic.len()
+      ),
+    )
+  }
+}
+
+impl std::fmt::Display for Clock {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let underline = "\u{005F}";
+    let rising = "/";
+    let overline = "\u{203E}";
+    let falling = "\\";
+    match self {
+      Clock::Rising => write!(f, "{}{}{}", underline, rising, overline),
+      Clock::High => write!(f, "{}{}{}", overline, overline, overline),
+      Clock::Falling => write!(f, "{}{}{}", overline, falling, underline),
+      Clock::Low => write!(f, "{}{}{}", underline, underline, underline),
+    }
+  }
+}
+
+impl std::fmt::Display for Reset {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Reset::Asserted => write!(f, "[#]"),
+      Reset::Deasserted => write!(f, "[ ]"),
+    }
+  }
+}
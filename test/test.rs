@@ -0,0 +1,1132 @@
+// golden tests, building every program with a `.golden` file in /test/golden/ and checking
+// its behavior on the instruction-level machine model against that file, then on the
+// component-level machine model in lockstep with it. with `ATTO8_ACCEPT=1`, golden files are
+// instead rewritten with the behavior observed. unit tests of the facilities `emu` and `sim`
+// build upon follow
+
+use atto8::common::*;
+use atto8::{asm, cc, emu, sim};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const DEFAULT_LIMIT: u128 = 10000000;
+
+struct Case {
+  sources: Vec<String>, // relative to the staging directory
+  stdin: Vec<u8>,
+  limit: u128, // clocks after which execution times out
+}
+
+struct Outcome {
+  stop: Stop,
+  clocks: u128,
+  stdout: Vec<u8>,
+  display: Option<[u8; DISPLAY_BUFFER_LEN]>, // not observed in lockstep
+}
+
+#[test]
+fn golden() {
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "golden");
+  let accept = std::env::var("ATTO8_ACCEPT").is_ok_and(|accept| accept == "1");
+
+  check_golden_files(root, accept, |case| {
+    emulate(&build(&staging, case)?, case, &staging)
+  });
+}
+
+#[test]
+fn golden_lockstep() {
+  // golden files describe `emu`, so outcomes observed in lockstep are never accepted
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "golden_lockstep");
+  let microcode_image = build_microcode(&staging);
+
+  check_golden_files(root, false, |case| {
+    simulate(&build(&staging, case)?, case, microcode_image)
+  });
+}
+
+#[test]
+fn lockstep() {
+  // `sim` runs the microcode of `rot` once per shift, all of which make up a single instruction
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "lockstep");
+  let microcode_image = build_microcode(&staging);
+
+  let source = "@ lib/core.asm\nmain!\n  x00 lda x00 lda rot\n  x00 sta\n  !hlt\n";
+  std::fs::write(staging.join("rot.asm"), source).unwrap();
+  let case = Case {
+    sources: vec!["rot.asm".to_string()],
+    stdin: vec![],
+    limit: DEFAULT_LIMIT,
+  };
+  let memory_image_file = build(&staging, &case).unwrap_or_else(|error| panic!("{}", error));
+
+  for (value, shift, expected) in [(0x05, 0x00, 0x05), (0x05, 0x03, 0x28), (0x81, 0x07, 0xC0)] {
+    let case = Case {
+      sources: vec![],
+      stdin: vec![value, shift],
+      limit: DEFAULT_LIMIT,
+    };
+    let outcome = simulate(&memory_image_file, &case, microcode_image)
+      .unwrap_or_else(|error| panic!("{}", error));
+
+    assert!(
+      matches!(outcome.stop, Stop::Halted),
+      "Test: Rotating {:02X} by {:02X} stopped with {:?}",
+      value,
+      shift,
+      outcome.stop
+    );
+    assert_eq!(outcome.stdout, [expected]);
+  }
+}
+
+#[test]
+fn snapshot() {
+  // running up to a snapshot then resuming from it behaves as running straight through, on both
+  // machine models. the snapshot is taken while standard input is partially consumed
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "snapshot");
+  let microcode_image = build_microcode(&staging);
+
+  let case = Case {
+    sources: vec!["rot13.asm".to_string()],
+    stdin: b"Hello, world!\n".to_vec(),
+    limit: 20000,
+  };
+  let memory_image_file = build(&staging, &case).unwrap_or_else(|error| panic!("{}", error));
+  let snapshot_file = staging.join("rot13.snp").to_string_lossy().to_string();
+
+  macro_rules! check {
+    ($mc:expr) => {{
+      let whole = Output::default();
+      let (stop, clocks) =
+        execute_batch($mc, batch(&case, &whole), None).unwrap_or_else(|error| panic!("{}", error));
+
+      let split = Output::default();
+      let mut first = batch(&case, &split);
+      first.max_clocks = Some(case.limit / 10);
+      first.snapshot_file = Some(snapshot_file.clone());
+      execute_batch($mc, first, None).unwrap_or_else(|error| panic!("{}", error));
+      let head = split.0.borrow().len();
+      let snapshot =
+        Snapshot::load(&snapshot_file, &$mc).unwrap_or_else(|error| panic!("{}", error));
+      assert!(!snapshot.stdin.is_empty() && snapshot.stdin.len() < case.stdin.len());
+      let mut second = batch(&case, &split);
+      second.stdin = vec![];
+      let (split_stop, split_clocks) =
+        execute_batch($mc, second, Some(snapshot)).unwrap_or_else(|error| panic!("{}", error));
+
+      assert!(head > 0 && head < whole.0.borrow().len());
+      assert_eq!(format!("{:?}", split_stop), format!("{:?}", stop));
+      assert_eq!(split_clocks, clocks);
+      assert_eq!(*split.0.borrow(), *whole.0.borrow());
+    }};
+  }
+
+  let (memory_image, _) = load(&memory_image_file).unwrap_or_else(|error| panic!("{}", error));
+  check!(emu::Microcomputer::new(memory_image));
+  check!(sim::Microcomputer::new(memory_image, microcode_image));
+}
+
+#[test]
+fn replay() {
+  // inputs are recorded as interactive mode records them, then replayed at the same clocks
+  let log = Output::default();
+  let mut recorder = Recorder {
+    file: Box::new(log.clone()),
+    controller: 0x00,
+  };
+  let mut inputs: Vec<(u128, Input)> = ((1000..).step_by(500).zip(b"Hello, world!\n"))
+    .map(|(clocks, c)| (clocks, Input::Stdin(*c)))
+    .collect();
+  inputs.extend([
+    (0, Input::Controller(0x00)),
+    (2000, Input::Controller(0x05)),
+    (2500, Input::Controller(0x05)),
+    (3000, Input::Controller(0x00)),
+  ]);
+  inputs.sort_by_key(|(clocks, _)| *clocks);
+  for (clocks, input) in inputs {
+    recorder
+      .record(clocks, input)
+      .unwrap_or_else(|error| panic!("{}", error));
+  }
+  let log = String::from_utf8(log.0.take()).unwrap();
+  assert_eq!(
+    log.lines().count(),
+    16,
+    "Test: Unchanged controller recorded"
+  );
+  assert!(log.starts_with("1000 stdin 48\n1500 stdin 65\n2000 stdin 6C\n2000 controller 05\n"));
+
+  let mut replay = parse_replay(&log).unwrap_or_else(|error| panic!("{}", error));
+  let mut stdin = VecDeque::new();
+  assert_eq!(replay.feed(999, &mut stdin), 0x00);
+  assert!(stdin.is_empty());
+  assert_eq!(replay.feed(2000, &mut stdin), 0x05);
+  assert_eq!(stdin, b"Hel");
+  assert_eq!(replay.feed(2999, &mut stdin), 0x05);
+  assert_eq!(replay.feed(3000, &mut stdin), 0x00);
+
+  for record in [
+    "1000 stdin",
+    "1000 keyboard 41",
+    "x stdin 41",
+    "1000 stdin 410",
+  ] {
+    assert!(
+      parse_replay(record).is_err(),
+      "Test: Invalid replay record '{}' accepted",
+      record
+    );
+  }
+
+  // a replay delivers the same bytes as standard input given up front, only later. controller
+  // records are left out as `rot13.asm` reads the controller state whenever `stdin` is empty
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "replay");
+  let case = Case {
+    sources: vec!["rot13.asm".to_string()],
+    stdin: b"Hello, world!\n".to_vec(),
+    limit: 20000,
+  };
+  let memory_image_file = build(&staging, &case).unwrap_or_else(|error| panic!("{}", error));
+  let expected =
+    emulate(&memory_image_file, &case, &staging).unwrap_or_else(|error| panic!("{}", error));
+  let replayed = Output::default();
+  let mut batch = batch(&case, &replayed);
+  batch.stdin = vec![];
+  let log: String = (log.lines())
+    .filter(|line| line.contains(" stdin "))
+    .map(|line| format!("{}\n", line))
+    .collect();
+  batch.replay = Some(parse_replay(&log).unwrap_or_else(|error| panic!("{}", error)));
+  let (memory_image, _) = load(&memory_image_file).unwrap_or_else(|error| panic!("{}", error));
+  execute_batch(emu::Microcomputer::new(memory_image), batch, None)
+    .unwrap_or_else(|error| panic!("{}", error));
+  let visible = |stdout: &[u8]| {
+    stdout
+      .iter()
+      .copied()
+      .filter(|c| *c != 0x00)
+      .collect::<Vec<u8>>()
+  };
+  assert_eq!(visible(&replayed.0.borrow()), visible(&expected.stdout));
+}
+
+#[test]
+fn sanitizer() {
+  // every finding of the sanitizer, first without it, then with it, then for self-modifying
+  // programs which only have stack reads checked
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "sanitizer");
+
+  let programs = [
+    (
+      "uninitialized read",
+      "x01 @dyn x02 @dyn pop @dyn pop @dyn\n  xFE @dyn sts ld0 !hlt",
+      [
+        "halted",
+        "trapped UninitializedRead",
+        "trapped UninitializedRead",
+      ],
+    ),
+    (
+      "code modification",
+      "x00 @dyn x01 @dyn sta\n  !hlt",
+      ["halted", "trapped CodeModification", "halted"],
+    ),
+    (
+      "data execution",
+      "xC0 :target sta\n  !jmp\n  target: @00",
+      ["timed out", "trapped DataExecution", "timed out"],
+    ),
+  ];
+
+  for (name, source, expected) in programs {
+    let source_file = format!("{}.asm", name);
+    let source = format!("@ lib/core.asm\nmain!\n  {}\n", source);
+    std::fs::write(staging.join(&source_file), source).unwrap();
+    let case = Case {
+      sources: vec![source_file],
+      stdin: vec![],
+      limit: 1000,
+    };
+    let memory_image_file = build(&staging, &case).unwrap_or_else(|error| panic!("{}", error));
+    let (memory_image, _) = load(&memory_image_file).unwrap_or_else(|error| panic!("{}", error));
+
+    for (self_modifying, expected) in [None, Some(false), Some(true)].into_iter().zip(expected) {
+      let mut mc = emu::Microcomputer::new(memory_image);
+      if let Some(self_modifying) = self_modifying {
+        mc.set_sanitizer(self_modifying);
+      }
+      let (stop, _) = execute_batch(mc, batch(&case, &Output::default()), None)
+        .unwrap_or_else(|error| panic!("{}", error));
+      let outcome = Outcome {
+        stop,
+        clocks: 0,
+        stdout: vec![],
+        display: None,
+      };
+      assert_eq!(
+        render_outcome(&outcome)[0],
+        format!("stop {}", expected),
+        "Test: {} with sanitizer {:?}",
+        name,
+        self_modifying
+      );
+    }
+  }
+}
+
+#[test]
+fn key_map() {
+  use console::Key;
+
+  let source = "# two players\nw secondary_up # comment\nq primary_up stdin\n\ndel\nbacktab switch_machine toggle_debug\n";
+  let key_map = parse_key_map(source).unwrap_or_else(|error| panic!("{}", error));
+  assert_eq!(key_map.actions(&Key::Char('w')), [KeyAction::Controller(4)]);
+  assert_eq!(
+    key_map.actions(&Key::Char('q')),
+    [KeyAction::Controller(0), KeyAction::Stdin]
+  );
+  assert_eq!(key_map.actions(&Key::Del), []);
+  assert_eq!(
+    key_map.actions(&Key::BackTab),
+    [KeyAction::SwitchMachine, KeyAction::ToggleDebug]
+  );
+  // bindings not overridden are kept
+  assert_eq!(
+    key_map.actions(&Key::Char('a')),
+    [KeyAction::Controller(6), KeyAction::Stdin]
+  );
+  assert_eq!(key_map.actions(&Key::End), [KeyAction::Controller(7)]);
+  assert_eq!(key_map.actions(&Key::Escape), [KeyAction::ToggleDebug]);
+  assert_eq!(key_map.actions(&Key::Char('x')), [KeyAction::Stdin]);
+
+  for record in [
+    "f12 stdin",
+    "ww stdin",
+    "w tertiary_up",
+    "w stdin,clear_stdout",
+  ] {
+    assert!(
+      parse_key_map(record).is_err(),
+      "Test: Invalid key map record '{}' accepted",
+      record
+    );
+  }
+}
+
+#[test]
+fn capture() {
+  // frames are encoded in every format, then decoded back and compared against the frames. the
+  // large noisy frame fills the GIF code table several times over
+  let staging = Path::new(env!("CARGO_TARGET_TMPDIR")).join("capture");
+  std::fs::remove_dir_all(&staging).ok();
+  std::fs::create_dir_all(&staging).unwrap();
+
+  let mut seed = 0x2545F491u32;
+  let mut noise = || {
+    seed ^= seed << 13;
+    seed ^= seed >> 17;
+    seed ^= seed << 5;
+    seed as u8
+  };
+  let lit: Levels = std::array::from_fn(|index| [0x00, 0xFF][(index * 7 % 11 < 5) as usize]);
+  let unlit: Levels = [0x00; 0x100];
+  let gray: Levels = std::array::from_fn(|_| noise());
+
+  let captures: [(CaptureFormat, usize, Vec<Levels>); 7] = [
+    (CaptureFormat::Pbm, 1, vec![lit, unlit]),
+    (CaptureFormat::Pgm, 3, vec![gray]),
+    (CaptureFormat::Png, 1, vec![lit]),
+    (CaptureFormat::Png, 3, vec![gray]),
+    (CaptureFormat::Gif, 2, vec![lit, unlit, lit]),
+    (CaptureFormat::Gif, 3, vec![gray, lit]),
+    (CaptureFormat::Gif, 16, vec![gray]),
+  ];
+
+  for (index, (format, scale, frames)) in captures.into_iter().enumerate() {
+    let extension = format!("{:?}", format).to_lowercase();
+    let file = staging.join(format!("{}.{}", index, extension));
+    let capture = Capture {
+      file: file.to_string_lossy().to_string(),
+      format,
+      interval: None,
+      scale,
+      clock_speed: 1000000,
+      frames: (0..).step_by(50000).zip(frames.clone()).collect(),
+    };
+    let clocks = 50000 * frames.len() as u128;
+    capture
+      .save(clocks)
+      .unwrap_or_else(|error| panic!("{}", error));
+
+    // frames as decoded, with levels quantized as each format quantizes them
+    let size = 0x10 * scale;
+    let gray = frames.iter().any(is_gray);
+    let decoded = match format {
+      CaptureFormat::Gif => decode_gif(&std::fs::read(&file).unwrap(), size, clocks / 10000),
+      _ => (0..frames.len())
+        .map(|frame| {
+          let file = staging.join(format!("{}-{:05}.{}", index, frame, extension));
+          let bytes = std::fs::read(file).unwrap();
+          match format {
+            CaptureFormat::Pbm => decode_pbm(&bytes, size),
+            CaptureFormat::Pgm => decode_pgm(&bytes, size),
+            _ => decode_png(&bytes, size),
+          }
+        })
+        .collect(),
+    };
+    let quantize = |level: u8| match (format, gray) {
+      (CaptureFormat::Pbm, _) | (_, false) => [0x00, 0xFF][(level >= 0x80) as usize],
+      (CaptureFormat::Gif, true) => (level >> 4) * 0x11,
+      (_, true) => level,
+    };
+    let expected: Vec<Vec<u8>> = (frames.iter())
+      .map(|levels| {
+        (0..size * size)
+          .map(|pixel| quantize(levels[pixel / size / scale * 0x10 + pixel % size / scale]))
+          .collect()
+      })
+      .collect();
+    assert!(
+      decoded == expected,
+      "Test: Capture {} as {:?} decoded differently",
+      index,
+      format
+    );
+  }
+}
+
+#[test]
+fn gdb() {
+  // a session as GDB drives it over TCP, `hello world.asm` running to completion
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let staging = stage(root, "gdb");
+  let case = Case {
+    sources: vec!["hello world.asm".to_string()],
+    stdin: vec![],
+    limit: DEFAULT_LIMIT,
+  };
+  let memory_image_file = build(&staging, &case).unwrap_or_else(|error| panic!("{}", error));
+  let (memory_image, _) = load(&memory_image_file).unwrap_or_else(|error| panic!("{}", error));
+  let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+    .unwrap()
+    .local_addr()
+    .unwrap()
+    .port();
+
+  let client = std::thread::spawn(move || {
+    let mut stream = (0..500)
+      .find_map(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::net::TcpStream::connect(("127.0.0.1", port)).ok()
+      })
+      .expect("Test: Unable to connect to GDB server");
+
+    let send = |stream: &mut std::net::TcpStream, packet: &str| {
+      let checksum = packet.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+      write!(stream, "${}#{:02x}", packet, checksum).unwrap();
+    };
+    let receive = |stream: &mut std::net::TcpStream| -> String {
+      // read byte by byte, as buffering would swallow whatever follows the packet
+      let mut bytes = std::iter::repeat_with(|| {
+        let mut byte = [0x00];
+        stream.read_exact(&mut byte).unwrap();
+        byte[0]
+      });
+      assert_eq!(bytes.next(), Some(b'$'));
+      let data: Vec<u8> = bytes.by_ref().take_while(|byte| *byte != b'#').collect();
+      let checksum: String = bytes.take(2).map(|byte| byte as char).collect();
+      assert_eq!(
+        u8::from_str_radix(&checksum, 16).unwrap(),
+        data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))
+      );
+      String::from_utf8(data).unwrap()
+    };
+    let ack = |stream: &mut std::net::TcpStream| {
+      let mut byte = [0x00];
+      stream.read_exact(&mut byte).unwrap();
+      byte[0]
+    };
+    macro_rules! exchange {
+      ($packet:expr) => {{
+        send(&mut stream, $packet);
+        receive(&mut stream)
+      }};
+    }
+
+    send(&mut stream, "?");
+    assert_eq!(ack(&mut stream), b'+');
+    assert_eq!(receive(&mut stream), "S05");
+    write!(stream, "$g#00").unwrap();
+    assert_eq!(ack(&mut stream), b'-');
+    send(&mut stream, "QStartNoAckMode");
+    assert_eq!(ack(&mut stream), b'+');
+    assert_eq!(receive(&mut stream), "OK");
+
+    let registers = exchange!("g");
+    assert!(registers.starts_with("00"));
+    assert_eq!(exchange!("p0"), "00");
+    assert_eq!(exchange!("p3"), "E01");
+    let expected: String = (memory_image[..4].iter())
+      .map(|byte| format!("{:02X}", byte))
+      .collect();
+    assert_eq!(exchange!("m0,4"), expected);
+    assert_eq!(exchange!("m100,1"), "E01");
+    assert_eq!(exchange!("M80,2:AA55"), "OK");
+    assert_eq!(exchange!("m80,2"), "AA55");
+    assert_eq!(exchange!("M80,2:AA"), "E01");
+
+    // step once, then run up to the same point again from a breakpoint
+    assert_eq!(exchange!("s"), "S05");
+    let stepped = exchange!("g");
+    assert_ne!(stepped, registers);
+    assert_eq!(exchange!(&format!("Z0,{},1", &stepped[..2])), "OK");
+    assert_eq!(exchange!(&format!("G{}", registers)), "OK");
+    assert_eq!(exchange!("c"), "S05");
+    assert_eq!(exchange!("g"), stepped);
+    assert_eq!(exchange!(&format!("z0,{},1", &stepped[..2])), "OK");
+
+    // run to completion, standard output forwarded to the GDB console
+    send(&mut stream, "c");
+    let mut console = vec![];
+    let stop = loop {
+      match receive(&mut stream) {
+        output if output.starts_with('O') => console.extend(
+          (1..output.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&output[index..index + 2], 16).unwrap()),
+        ),
+        stop => break stop,
+      }
+    };
+    assert_eq!(stop, "W00");
+    assert_eq!(console, b"Hello, world!\x00");
+  });
+
+  let result = execute_gdb(
+    "Test",
+    emu::Microcomputer::new(memory_image),
+    &port.to_string(),
+    vec![],
+    None,
+  );
+  client.join().unwrap();
+  result.unwrap_or_else(|error| panic!("{}", error));
+}
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>); // standard output shared with the test once boxed
+
+impl Write for Output {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+fn check_golden_files(root: &Path, accept: bool, run: impl Fn(&Case) -> Result<Outcome, Error>) {
+  let mut golden_files: Vec<PathBuf> = std::fs::read_dir(root.join("test/golden"))
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| {
+      path
+        .extension()
+        .is_some_and(|extension| extension == "golden")
+    })
+    .collect();
+  golden_files.sort();
+  assert!(
+    !golden_files.is_empty(),
+    "Test: Error: No golden files found"
+  );
+
+  let mut failures = vec![];
+  for golden_file in &golden_files {
+    let name = golden_file
+      .file_stem()
+      .unwrap()
+      .to_string_lossy()
+      .to_string();
+    let golden = std::fs::read_to_string(golden_file).unwrap();
+
+    match parse_case(&golden).and_then(|case| run(&case)) {
+      Ok(outcome) => {
+        let expected = outcome_records(&golden, outcome.display.is_some());
+        let actual = render_outcome(&outcome);
+        match (expected == actual, accept) {
+          (true, _) => (),
+          (false, true) => {
+            std::fs::write(golden_file, accept_outcome(&golden, &actual)).unwrap();
+            println!("Test: {}: Accepted\n{}", name, diff(&expected, &actual));
+          }
+          (false, false) => {
+            failures.push(format!(
+              "Test: {}: Mismatch\n{}",
+              name,
+              diff(&expected, &actual)
+            ));
+          }
+        }
+      }
+      Err(error) => failures.push(format!("Test: {}: Error: {}", name, error)),
+    }
+  }
+
+  if !failures.is_empty() {
+    panic!(
+      "{}\nTest: {} of {} golden files failed, rerun with `ATTO8_ACCEPT=1` to accept",
+      failures.join("\n"),
+      failures.len(),
+      golden_files.len()
+    );
+  }
+}
+
+fn build_microcode(staging: &Path) -> [u16; MIC_SIZE] {
+  // through the `mic` binary, as the microcode builder is not part of the library
+  let microcode_image_file = staging.join("microcode.mic");
+  let status = std::process::Command::new(env!("CARGO_BIN_EXE_mic"))
+    .arg(&microcode_image_file)
+    .stdout(std::process::Stdio::null())
+    .status()
+    .unwrap();
+  assert!(status.success(), "Test: Error: Unable to build microcode");
+
+  (std::fs::read(microcode_image_file).unwrap())
+    .chunks(2)
+    .map(|chunk| u16::from_le_bytes(chunk.try_into().unwrap()))
+    .collect::<Vec<u16>>()
+    .try_into()
+    .unwrap()
+}
+
+fn stage(root: &Path, name: &str) -> PathBuf {
+  // lay out sources as `test.py` does, as includes are resolved relative to the including file
+
+  fn copy_dir(src: &Path, dst: &Path) {
+    std::fs::create_dir_all(dst).unwrap();
+    for entry in std::fs::read_dir(src).unwrap() {
+      let entry = entry.unwrap();
+      match entry.file_type().unwrap().is_dir() {
+        true => copy_dir(&entry.path(), &dst.join(entry.file_name())),
+        false => drop(std::fs::copy(entry.path(), dst.join(entry.file_name())).unwrap()),
+      }
+    }
+  }
+
+  let staging = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+  std::fs::remove_dir_all(&staging).ok();
+  copy_dir(&root.join("lib"), &staging.join("lib"));
+  copy_dir(&root.join("libc"), &staging.join("libc"));
+  copy_dir(&root.join("misc"), &staging.join("misc"));
+  for dir in [
+    "test/musts",
+    "test/utils",
+    "test/games",
+    "test/other",
+    "test/tests",
+    "libc/incl",
+  ] {
+    copy_dir(&root.join(dir), &staging);
+  }
+  staging
+}
+
+fn parse_case(golden: &str) -> Result<Case, Error> {
+  let mut case = Case {
+    sources: vec![],
+    stdin: vec![],
+    limit: DEFAULT_LIMIT,
+  };
+
+  for line in golden.lines() {
+    if line.starts_with('#') || line.trim().is_empty() {
+      continue;
+    }
+
+    let (keyword, value) = line.split_once(' ').unwrap_or((line, ""));
+    match keyword {
+      "source" => case.sources.push(value.to_string()),
+      "stdin" => case.stdin.extend(unescape(value)?),
+      "limit" => {
+        case.limit = value
+          .parse()
+          .map_err(|_| Error(format!("Invalid clock limit '{}'", value)))?
+      }
+      "stop" | "clocks" | "stdout" | "display" => (),
+      _ => Err(Error(format!("Invalid golden record '{}'", line)))?,
+    }
+  }
+
+  if case.sources.is_empty() {
+    Err(Error("No source files".to_string()))?;
+  }
+  Ok(case)
+}
+
+fn build(staging: &Path, case: &Case) -> Result<PathBuf, Error> {
+  // C sources go through `cc` then `asm`, assembly sources through `asm`, hex sources through
+  // `enc`. returns the memory image file written into the staging directory
+
+  fn report(errors: &[(Pos, Error)]) -> Error {
+    let errors = (errors.iter())
+      .map(|(pos, error)| format!("{}: {}", pos, error))
+      .collect::<Vec<String>>()
+      .join("\n");
+    Error(errors)
+  }
+
+  let mut errors: Vec<(Pos, Error)> = vec![];
+  let memory_image_file = staging.join(format!("{}.mem", case.sources[0]));
+  let files: Vec<File> = (case.sources.iter())
+    .map(|source| File(staging.join(source)))
+    .collect();
+  let assembly_source_file = match case.sources[0].rsplit_once('.') {
+    Some((_, "c")) => {
+      let assembly = cc::compile(files, &mut errors);
+      if !errors.is_empty() {
+        Err(report(&errors))?;
+      }
+      let assembly_source_file = staging.join(format!("{}.asm", case.sources[0]));
+      std::fs::write(&assembly_source_file, assembly).unwrap();
+      File(assembly_source_file)
+    }
+    Some((_, "asm")) => files.into_iter().next().unwrap(),
+    Some((_, "hex")) => {
+      encode(&files[0].0, &memory_image_file)?;
+      return Ok(memory_image_file);
+    }
+    _ => Err(Error(format!(
+      "Unknown source file type '{}'",
+      case.sources[0]
+    )))?,
+  };
+
+  let bank_size = parse_bank_size(&BTreeMap::new())?;
+  let (memory_image, _) = asm::build(assembly_source_file, bank_size, &mut errors);
+  if !errors.is_empty() {
+    Err(report(&errors))?;
+  }
+  std::fs::write(&memory_image_file, memory_image).unwrap();
+  Ok(memory_image_file)
+}
+
+fn encode(hex_source_file: &Path, memory_image_file: &Path) -> Result<(), Error> {
+  // through `enc`, a Python script that imports `common` relative to its own directory
+  let enc = Path::new(env!("CARGO_MANIFEST_DIR")).join("enc");
+  let output = std::process::Command::new("python3")
+    .arg(enc.join("enc.py"))
+    .arg(hex_source_file)
+    .arg(memory_image_file)
+    .current_dir(&enc)
+    .output()
+    .map_err(|_| Error("Unable to run `enc`".to_string()))?;
+  match output.status.success() {
+    true => Ok(()),
+    false => Err(Error(
+      String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    )),
+  }
+}
+
+fn load(memory_image_file: &Path) -> Result<([u8; MEM_SIZE], Option<Banks>), Error> {
+  load_memory_image(&memory_image_file.to_string_lossy(), &BTreeMap::new())
+}
+
+fn batch(case: &Case, stdout: &Output) -> Batch {
+  Batch {
+    stdin: case.stdin.clone(),
+    stdout: Box::new(stdout.clone()),
+    max_clocks: Some(case.limit),
+    max_instructions: None,
+    snapshot_file: None,
+    trace: None,
+    profile: None,
+    profile_tokens: false,
+    debug_info: None,
+    replay: None,
+    capture: None,
+    phosphor: None,
+  }
+}
+
+fn emulate(memory_image_file: &Path, case: &Case, staging: &Path) -> Result<Outcome, Error> {
+  // as `emu --batch` runs, reading the final display back from the snapshot taken on stop
+
+  let emu = || -> Result<emu::Microcomputer, Error> {
+    let (memory_image, banks) = load(memory_image_file)?;
+    let mut mc = emu::Microcomputer::new(memory_image);
+    if let Some(banks) = banks {
+      mc.bus_mut().attach(Box::new(banks));
+    }
+    Ok(mc)
+  };
+
+  let stdout = Output::default();
+  let snapshot_file = staging.join("golden.snp").to_string_lossy().to_string();
+  let mut batch = batch(case, &stdout);
+  batch.snapshot_file = Some(snapshot_file.clone());
+  let (stop, clocks) = execute_batch(emu()?, batch, None)?;
+  let snapshot = Snapshot::load(&snapshot_file, &emu()?)?;
+
+  Ok(Outcome {
+    stop,
+    clocks,
+    stdout: stdout.0.take(),
+    display: Some(snapshot.display),
+  })
+}
+
+fn simulate(
+  memory_image_file: &Path,
+  case: &Case,
+  microcode_image: [u16; MIC_SIZE],
+) -> Result<Outcome, Error> {
+  // as `sim --lockstep` runs, `emu` as the reference and `sim` as the subject
+  let (memory_image, banks) = load(memory_image_file)?;
+  let mut reference = emu::Microcomputer::new(memory_image);
+  let mut subject = sim::Microcomputer::new(memory_image, microcode_image);
+  if let Some(banks) = banks {
+    subject.bus_mut().attach(Box::new(banks.clone()));
+    reference.bus_mut().attach(Box::new(banks));
+  }
+
+  let stdout = Output::default();
+  let (stop, clocks) = execute_lockstep("Test", reference, subject, batch(case, &stdout))?;
+
+  Ok(Outcome {
+    stop,
+    clocks,
+    stdout: stdout.0.take(),
+    display: None,
+  })
+}
+
+fn outcome_records(golden: &str, display: bool) -> Vec<String> {
+  (golden.lines())
+    .filter(|line| {
+      let keyword = line.split(' ').next().unwrap();
+      matches!(keyword, "stop" | "clocks" | "stdout") || display && keyword == "display"
+    })
+    .map(String::from)
+    .collect()
+}
+
+fn render_outcome(outcome: &Outcome) -> Vec<String> {
+  let mut records = vec![];
+
+  records.push(match outcome.stop {
+    Stop::Halted => "stop halted".to_string(),
+    Stop::Trapped(tick_trap) => format!("stop trapped {:?}", tick_trap),
+    Stop::TimedOut => "stop timed out".to_string(),
+    Stop::Diverged => "stop diverged".to_string(),
+  });
+  records.push(format!("clocks {}", outcome.clocks));
+
+  // one record per line of output, newlines included. NULs are dropped, as by the emulator
+  let stdout: Vec<u8> = outcome
+    .stdout
+    .iter()
+    .copied()
+    .filter(|c| *c != 0x00)
+    .collect();
+  for line in stdout.split_inclusive(|c| *c == b'\n') {
+    records.push(format!("stdout {}", escape(line)));
+  }
+
+  // one record per line of the display as rendered by the emulator, trailing whitespace trimmed
+  if let Some(display) = &outcome.display {
+    for line in render_display(display).lines() {
+      if !line.is_empty() {
+        records.push(format!("display {}", line).trim_end().to_string());
+      }
+    }
+  }
+
+  records
+}
+
+fn accept_outcome(golden: &str, actual: &[String]) -> String {
+  // keep comments and records describing the program, then replace the expected outcome
+  let description: Vec<&str> = (golden.lines())
+    .filter(|line| {
+      !matches!(
+        line.split(' ').next().unwrap(),
+        "stop" | "clocks" | "stdout" | "display"
+      )
+    })
+    .collect();
+  let description = description.join("\n");
+  format!("{}\n{}\n", description.trim_end(), actual.join("\n"))
+}
+
+fn escape(bytes: &[u8]) -> String {
+  // printable ASCII is kept as is, save for backslashes and a trailing space
+  (bytes.iter().enumerate())
+    .map(|(index, byte)| match byte {
+      b'\\' => "\\\\".to_string(),
+      b'\n' => "\\n".to_string(),
+      b'\t' => "\\t".to_string(),
+      b' ' if index == bytes.len() - 1 => "\\x20".to_string(),
+      b' '..=b'~' => (*byte as char).to_string(),
+      byte => format!("\\x{:02X}", byte),
+    })
+    .collect()
+}
+
+fn unescape(string: &str) -> Result<Vec<u8>, Error> {
+  let invalid = || Error(format!("Invalid escape sequence in '{}'", string));
+  let mut bytes = vec![];
+  let mut chars = string.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => match chars.next() {
+        Some('\\') => bytes.push(b'\\'),
+        Some('n') => bytes.push(b'\n'),
+        Some('t') => bytes.push(b'\t'),
+        Some('x') => {
+          let hex: String = chars.by_ref().take(2).collect();
+          bytes.push(u8::from_str_radix(&hex, 16).map_err(|_| invalid())?);
+        }
+        _ => Err(invalid())?,
+      },
+      c if c.is_ascii() => bytes.push(c as u8),
+      _ => Err(invalid())?,
+    }
+  }
+
+  Ok(bytes)
+}
+
+fn diff(expected: &[String], actual: &[String]) -> String {
+  // line diff through a longest common subsequence, `-` for expected and `+` for actual
+
+  let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+  for i in (0..expected.len()).rev() {
+    for j in (0..actual.len()).rev() {
+      lcs[i][j] = match expected[i] == actual[j] {
+        true => lcs[i + 1][j + 1] + 1,
+        false => lcs[i + 1][j].max(lcs[i][j + 1]),
+      };
+    }
+  }
+
+  let mut lines = vec![];
+  let (mut i, mut j) = (0, 0);
+  while i < expected.len() || j < actual.len() {
+    if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+      lines.push(format!("  {}", expected[i]));
+      (i, j) = (i + 1, j + 1);
+    } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+      lines.push(format!("- {}", expected[i]));
+      i += 1;
+    } else {
+      lines.push(format!("+ {}", actual[j]));
+      j += 1;
+    }
+  }
+
+  lines.join("\n")
+}
+
+fn decode_pbm(bytes: &[u8], size: usize) -> Vec<u8> {
+  // https://netpbm.sourceforge.net/doc/pbm.html
+  let header = format!("P4\n{} {}\n", size, size);
+  assert!(bytes.starts_with(header.as_bytes()));
+  let rows = bytes[header.len()..].chunks(size.div_ceil(8));
+  assert_eq!(rows.len(), size);
+  rows
+    .flat_map(|row| (0..size).map(move |x| [0xFF, 0x00][(row[x / 8] >> (7 - x % 8) & 1) as usize]))
+    .collect()
+}
+
+fn decode_pgm(bytes: &[u8], size: usize) -> Vec<u8> {
+  // https://netpbm.sourceforge.net/doc/pgm.html
+  let header = format!("P5\n{} {}\n255\n", size, size);
+  assert!(bytes.starts_with(header.as_bytes()));
+  assert_eq!(bytes.len(), header.len() + size * size);
+  bytes[header.len()..].to_vec()
+}
+
+fn decode_png(bytes: &[u8], size: usize) -> Vec<u8> {
+  // https://www.w3.org/TR/png/
+  // only what the encoder emits: grayscale without interlacing, filter type 0, stored blocks
+
+  fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+      crc ^= *byte as u32;
+      for _ in 0..8 {
+        crc = match crc & 1 {
+          1 => crc >> 1 ^ 0xEDB88320,
+          _ => crc >> 1,
+        };
+      }
+    }
+    !crc
+  }
+
+  assert!(bytes.starts_with(b"\x89PNG\r\n\x1A\n"));
+  let mut rest = &bytes[8..];
+  let mut chunks = vec![];
+  while !rest.is_empty() {
+    let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+    let body = &rest[4..8 + len];
+    let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+    assert_eq!(crc, crc32(body), "Test: PNG chunk CRC mismatch");
+    chunks.push((&body[..4], &body[4..]));
+    rest = &rest[12 + len..];
+  }
+  let types: Vec<&[u8]> = chunks.iter().map(|(r#type, _)| *r#type).collect();
+  assert_eq!(types, [&b"IHDR"[..], b"IDAT", b"IEND"]);
+
+  let ihdr = chunks[0].1;
+  assert_eq!(
+    ihdr[..8],
+    [(size as u32).to_be_bytes(), (size as u32).to_be_bytes()].concat()
+  );
+  assert_eq!(ihdr[9..], [0x00; 4]);
+  let depth = ihdr[8] as usize;
+
+  let zlib = chunks[1].1;
+  assert_eq!(zlib[..2], [0x78, 0x01]);
+  let (mut rest, mut raw) = (&zlib[2..], vec![]);
+  loop {
+    let last = rest[0] == 0x01;
+    let len = u16::from_le_bytes([rest[1], rest[2]]);
+    assert_eq!(!len, u16::from_le_bytes([rest[3], rest[4]]));
+    raw.extend(&rest[5..5 + len as usize]);
+    rest = &rest[5 + len as usize..];
+    if last {
+      break;
+    }
+  }
+  let (a, b) = raw.iter().fold((1u32, 0u32), |(a, b), byte| {
+    let a = (a + *byte as u32) % 65521;
+    (a, (b + a) % 65521)
+  });
+  assert_eq!(rest, (b << 16 | a).to_be_bytes());
+
+  let stride = (size * depth).div_ceil(8);
+  let rows = raw.chunks(1 + stride);
+  assert_eq!(rows.len(), size);
+  rows
+    .flat_map(|row| {
+      assert_eq!(row[0], 0x00);
+      (0..size).map(move |x| match depth {
+        1 => [0x00, 0xFF][(row[1 + x / 8] >> (7 - x % 8) & 1) as usize],
+        _ => row[1 + x],
+      })
+    })
+    .collect()
+}
+
+fn decode_gif(bytes: &[u8], size: usize, centiseconds: u128) -> Vec<Vec<u8>> {
+  // https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+  // only what the encoder emits: one global palette, a looping extension, then full frames.
+  // `centiseconds` is the length of the whole animation
+
+  fn decode_lzw(data: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1usize << min_code_size;
+    let reset = || -> Vec<Vec<u8>> { (0..clear + 2).map(|code| vec![code as u8]).collect() };
+
+    let mut table = reset();
+    let mut width = min_code_size + 1;
+    let (mut acc, mut bits) = (0u32, 0);
+    let mut data = data.iter();
+    let mut previous: Option<Vec<u8>> = None;
+    let mut indices = vec![];
+    loop {
+      while bits < width {
+        acc |= (*data.next().expect("Test: GIF data ended before EOI") as u32) << bits;
+        bits += 8;
+      }
+      let code = (acc & ((1 << width) - 1)) as usize;
+      (acc, bits) = (acc >> width, bits - width);
+
+      if code == clear {
+        (table, width, previous) = (reset(), min_code_size + 1, None);
+        continue;
+      }
+      if code == clear + 1 {
+        break;
+      }
+      let entry = match (table.get(code), &previous) {
+        (Some(entry), _) => entry.clone(),
+        (None, Some(previous)) if code == table.len() => [&previous[..], &previous[..1]].concat(),
+        _ => panic!("Test: Invalid GIF code {:03X}", code),
+      };
+      if let Some(previous) = previous {
+        if table.len() < 0x1000 {
+          table.push([&previous[..], &entry[..1]].concat());
+        }
+      }
+      if table.len() == 1 << width && width < 12 {
+        width += 1;
+      }
+      indices.extend(&entry);
+      previous = Some(entry);
+    }
+    indices
+  }
+
+  assert!(bytes.starts_with(b"GIF89a"));
+  let screen = (size as u16).to_le_bytes();
+  assert_eq!(bytes[6..10], [screen, screen].concat());
+  let bits = (bytes[10] & 0x07) + 1;
+  let palette: Vec<u8> = (bytes[13..13 + 3 * (1 << bits)].chunks(3))
+    .map(|color| {
+      assert!(color[0] == color[1] && color[1] == color[2]);
+      color[0]
+    })
+    .collect();
+  let mut rest = &bytes[13 + 3 * (1 << bits)..];
+  assert!(rest.starts_with(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00"));
+  rest = &rest[19..];
+
+  let mut frames = vec![];
+  let mut delays = 0;
+  while rest[0] != 0x3B {
+    assert_eq!(rest[..4], [0x21, 0xF9, 0x04, 0x00]);
+    delays += u16::from_le_bytes([rest[4], rest[5]]) as u128;
+    assert_eq!(rest[6..13], [0x00, 0x00, 0x2C, 0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(rest[13..17], [screen, screen].concat());
+    assert_eq!(rest[17], 0x00);
+    let min_code_size = rest[18];
+    rest = &rest[19..];
+
+    let mut data = vec![];
+    while rest[0] != 0x00 {
+      data.extend(&rest[1..1 + rest[0] as usize]);
+      rest = &rest[1 + rest[0] as usize..];
+    }
+    rest = &rest[1..];
+
+    let indices = decode_lzw(&data, min_code_size);
+    assert_eq!(indices.len(), size * size);
+    frames.push(
+      indices
+        .iter()
+        .map(|index| palette[*index as usize])
+        .collect(),
+    );
+  }
+  assert_eq!(rest, [0x3B]);
+  assert_eq!(delays, centiseconds);
+  frames
+}